@@ -0,0 +1,95 @@
+// 바쁜 채널에서 메세지마다 transaction을 여는 경우와 writer가 모아서 쓰는 경우 비교
+// cargo bench --bench ingest
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use discord_search_bot::{database, ingest, settings, storage};
+use poise::serenity_prelude::{ChannelId, GuildId, Message, MessageId};
+use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const MESSAGES: u64 = 1_000;
+const CHANNEL_ID: u64 = 10;
+const GUILD_ID: u64 = 100;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn message(id: u64) -> Message {
+    let mut msg = Message::default();
+    msg.id = MessageId::new(id);
+    msg.channel_id = ChannelId::new(CHANNEL_ID);
+    msg.guild_id = Some(GuildId::new(GUILD_ID));
+    msg.content = format!("안녕하세요 benchmark message {id}");
+    msg
+}
+
+async fn file_pool(dir: &tempfile::TempDir) -> SqlitePool {
+    let options = SqliteConnectOptions::new()
+        .filename(dir.path().join("bench.db"))
+        .create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect_with(options)
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
+
+// 이전 방식: 메세지마다 insert transaction + range transaction
+async fn per_event(pool: &SqlitePool) {
+    let start = NEXT_ID.fetch_add(MESSAGES, Ordering::Relaxed);
+    for id in start..start + MESSAGES {
+        database::insert_messages(pool, &[message(id)], GUILD_ID as i64, &Default::default())
+            .await
+            .unwrap();
+        database::add_sync_range(pool, CHANNEL_ID as i64, start as i64, id as i64)
+            .await
+            .unwrap();
+    }
+}
+
+async fn batched(ingest: &ingest::Ingest) {
+    let start = NEXT_ID.fetch_add(MESSAGES, Ordering::Relaxed);
+    for id in start..start + MESSAGES {
+        ingest
+            .send(ingest::Write::Insert(Box::new(message(id))))
+            .await
+            .unwrap();
+        ingest
+            .send(ingest::Write::Range {
+                channel_id: ChannelId::new(CHANNEL_ID),
+                range: database::Range::new(start as i64, id as i64),
+            })
+            .await
+            .unwrap();
+    }
+    ingest.flush().await.unwrap();
+}
+
+fn busy_channel(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let pool = rt.block_on(file_pool(&dir));
+    let ingest = rt.block_on(async {
+        let storage = std::sync::Arc::new(storage::SqliteStorage::new(pool.clone()));
+        let settings = settings::Settings::new(storage.clone());
+        ingest::Ingest::spawn(
+            storage,
+            settings,
+            Default::default(),
+            Duration::from_millis(100),
+            10_000,
+        )
+    });
+
+    let mut group = c.benchmark_group("busy_channel");
+    group.throughput(Throughput::Elements(MESSAGES));
+    group.sample_size(10);
+    group.bench_function("per_event", |b| b.to_async(&rt).iter(|| per_event(&pool)));
+    group.bench_function("batched", |b| b.to_async(&rt).iter(|| batched(&ingest)));
+    group.finish();
+}
+
+criterion_group!(benches, busy_channel);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS backfill_state (
+    channel_id INTEGER PRIMARY KEY,
+    guild_id INTEGER NOT NULL,
+    -- 지금까지 가져온 가장 오래된 message id. NULL이면 아직 시작 안함
+    cursor_id INTEGER,
+    status TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,18 @@
+-- 수정되기 전 메세지 내용. edited_at은 이 내용이 수정된 시각
+CREATE TABLE IF NOT EXISTS message_revisions (
+    revision_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    edited_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_revisions_message ON message_revisions (message_id);
+CREATE INDEX IF NOT EXISTS idx_message_revisions_guild ON message_revisions (guild_id);
+
+-- 원본 메세지가 지워지면 수정 기록도 같이 지움
+-- INSERT OR REPLACE는 delete trigger를 타지 않으므로 재캐싱해도 기록은 유지됨
+CREATE TRIGGER IF NOT EXISTS ad_messages_revisions AFTER DELETE ON messages BEGIN
+    DELETE FROM message_revisions WHERE message_id = old.message_id;
+END;
@@ -0,0 +1,11 @@
+-- 검색 결과에 보여줄 작성자 표시 이름. 메세지를 캐싱할 때 갱신하고 GuildMemberUpdate로 최신화
+CREATE TABLE IF NOT EXISTS members (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    global_name TEXT,
+    nick TEXT,
+    avatar_url TEXT,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);
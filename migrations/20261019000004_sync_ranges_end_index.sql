@@ -0,0 +1,2 @@
+-- 새 range와 맞닿은 range만 찾기 위한 index. start_id는 PK로 찾음
+CREATE INDEX IF NOT EXISTS idx_sync_ranges_end ON sync_ranges (channel_id, end_id);
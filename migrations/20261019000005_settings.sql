@@ -0,0 +1,43 @@
+-- 문자열 key/value였던 config를 서버, 채널별 타입이 있는 설정으로 옮김
+CREATE TABLE IF NOT EXISTS settings (
+    -- 채널 설정이면 채널이 속한 서버. 옮길 때 알 수 없으면 0
+    guild_id INTEGER NOT NULL,
+    -- 0이면 서버 설정
+    channel_id INTEGER NOT NULL DEFAULT 0,
+    setting TEXT NOT NULL,
+    bool_value INTEGER,
+    int_value INTEGER,
+    text_value TEXT,
+    PRIMARY KEY (guild_id, channel_id, setting)
+);
+
+CREATE INDEX IF NOT EXISTS idx_settings_channel ON settings (channel_id, setting);
+
+-- key는 "{guild|channel}:{id}:{setting}"
+INSERT OR REPLACE INTO settings (guild_id, channel_id, setting, bool_value, int_value, text_value)
+SELECT
+    CASE scope
+        WHEN 'guild' THEN scope_id
+        ELSE COALESCE(
+            (SELECT b.guild_id FROM backfill_state b WHERE b.channel_id = scope_id),
+            (SELECT m.guild_id FROM messages m WHERE m.channel_id = scope_id LIMIT 1),
+            0
+        )
+    END,
+    CASE scope WHEN 'channel' THEN scope_id ELSE 0 END,
+    setting,
+    CASE WHEN setting IN ('caching', 'revisions') THEN value = 'true' END,
+    CASE WHEN setting IN ('retention_days', 'retention_count') THEN CAST(value AS INTEGER) END,
+    CASE WHEN setting IN ('bots', 'bot_allowlist') THEN value END
+FROM (
+    SELECT
+        substr(key, 1, instr(key, ':') - 1) AS scope,
+        CAST(substr(rest, 1, instr(rest, ':') - 1) AS INTEGER) AS scope_id,
+        substr(rest, instr(rest, ':') + 1) AS setting,
+        value
+    FROM (SELECT key, value, substr(key, instr(key, ':') + 1) AS rest FROM config)
+)
+WHERE scope IN ('guild', 'channel')
+  AND setting IN ('caching', 'revisions', 'retention_days', 'retention_count', 'bots', 'bot_allowlist');
+
+DROP TABLE config;
@@ -0,0 +1,5 @@
+-- /privacy forget-me로 캐싱을 거부한 사용자. 이후 이 사용자의 메세지는 저장하지 않음
+CREATE TABLE IF NOT EXISTS opted_out_users (
+    user_id INTEGER PRIMARY KEY,
+    opted_out_at INTEGER NOT NULL
+);
@@ -0,0 +1,87 @@
+-- SQLite 마이그레이션을 모두 적용한 상태와 같은 스키마
+-- 검색은 SQLite의 LIKE와 같이 부분 문자열로 찾고, pg_trgm index로 빠르게 함
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE TABLE IF NOT EXISTS messages (
+    message_id BIGINT PRIMARY KEY,
+    channel_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    author_id BIGINT NOT NULL,
+    author_name TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    reply_to_id BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS idx_messages_channel_created ON messages (channel_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_messages_guild ON messages (guild_id);
+CREATE INDEX IF NOT EXISTS idx_messages_author ON messages (author_id);
+CREATE INDEX IF NOT EXISTS idx_messages_reply_to ON messages (reply_to_id);
+CREATE INDEX IF NOT EXISTS idx_messages_content_trgm ON messages USING GIN (content gin_trgm_ops);
+
+CREATE TABLE IF NOT EXISTS message_revisions (
+    revision_id BIGSERIAL PRIMARY KEY,
+    message_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    content TEXT NOT NULL,
+    edited_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_revisions_message ON message_revisions (message_id);
+CREATE INDEX IF NOT EXISTS idx_message_revisions_guild ON message_revisions (guild_id);
+CREATE INDEX IF NOT EXISTS idx_message_revisions_content_trgm ON message_revisions USING GIN (content gin_trgm_ops);
+
+-- 원본 메세지가 지워지면 수정 기록도 같이 지움. 재캐싱은 ON CONFLICT UPDATE라 기록이 유지됨
+CREATE OR REPLACE FUNCTION delete_message_revisions() RETURNS trigger AS $$
+BEGIN
+    DELETE FROM message_revisions WHERE message_id = OLD.message_id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER ad_messages_revisions AFTER DELETE ON messages
+    FOR EACH ROW EXECUTE FUNCTION delete_message_revisions();
+
+CREATE TABLE IF NOT EXISTS members (
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    username TEXT NOT NULL,
+    global_name TEXT,
+    nick TEXT,
+    avatar_url TEXT,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS sync_ranges (
+    channel_id BIGINT NOT NULL,
+    start_id BIGINT NOT NULL,
+    end_id BIGINT NOT NULL,
+    PRIMARY KEY (channel_id, start_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_ranges_end ON sync_ranges (channel_id, end_id);
+
+CREATE TABLE IF NOT EXISTS settings (
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL DEFAULT 0,
+    setting TEXT NOT NULL,
+    bool_value BOOLEAN,
+    int_value BIGINT,
+    text_value TEXT,
+    PRIMARY KEY (guild_id, channel_id, setting)
+);
+
+CREATE INDEX IF NOT EXISTS idx_settings_channel ON settings (channel_id, setting);
+
+CREATE TABLE IF NOT EXISTS version_subscriptions (
+    user_id BIGINT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    last_notified_version TEXT
+);
+
+CREATE TABLE IF NOT EXISTS opted_out_users (
+    user_id BIGINT PRIMARY KEY,
+    opted_out_at BIGINT NOT NULL
+);
@@ -0,0 +1,316 @@
+use crate::redaction::Redactor;
+use crate::{Error, database};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use poise::serenity_prelude::{ChannelId, GuildId};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::io::{BufRead, BufReader, Write};
+
+// 채널 캐시 아카이브: gzip으로 압축한 JSONL
+// 첫 줄은 header, 그 뒤로 sync range와 메세지가 한 줄씩
+pub const FORMAT_VERSION: u32 = 1;
+
+const PAGE_SIZE: i64 = 1_000; // DB에서 한 번에 읽고, import 때 transaction 하나에 넣을 메세지 수
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub guild_id: u64,
+    pub channel_id: u64,
+    pub exported_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Header(Header),
+    Range { start: i64, end: i64 },
+    Message(database::ArchivedMessage),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub messages: u64, // 아카이브에 있던 메세지
+    pub inserted: u64, // 새로 캐시된 메세지
+    pub ranges: usize,
+}
+
+// 압축된 크기가 max_bytes를 넘으면 중단. discord 첨부파일 크기 제한 때문
+pub async fn export(
+    pool: &SqlitePool,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    max_bytes: usize,
+) -> Result<Vec<u8>, Error> {
+    let channel = channel_id.get() as i64;
+    let mut out = GzEncoder::new(Vec::new(), Compression::default());
+
+    write_record(
+        &mut out,
+        &Record::Header(Header {
+            version: FORMAT_VERSION,
+            guild_id: guild_id.get(),
+            channel_id: channel_id.get(),
+            exported_at: chrono::Utc::now().timestamp(),
+        }),
+    )?;
+    for range in database::list_sync_ranges(pool, channel).await? {
+        write_record(
+            &mut out,
+            &Record::Range {
+                start: range.start,
+                end: range.end,
+            },
+        )?;
+    }
+
+    let mut after_id = 0;
+    loop {
+        let page = database::list_archived_messages(pool, channel, after_id, PAGE_SIZE).await?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        after_id = last.message_id;
+        for msg in page {
+            write_record(&mut out, &Record::Message(msg))?;
+        }
+        if out.get_ref().len() > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+    }
+
+    let bytes = out.finish()?;
+    if bytes.len() > max_bytes {
+        return Err(too_large(max_bytes));
+    }
+    Ok(bytes)
+}
+
+fn write_record(out: &mut impl Write, record: &Record) -> Result<(), Error> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+fn too_large(max_bytes: usize) -> Error {
+    format!("archive is larger than {} MiB", max_bytes / (1024 * 1024)).into()
+}
+
+pub struct Archive<'a> {
+    header: Header,
+    lines: std::io::Lines<BufReader<GzDecoder<&'a [u8]>>>,
+}
+
+impl<'a> Archive<'a> {
+    // header만 먼저 읽어서 어느 채널의 아카이브인지 확인할 수 있게 함
+    pub fn open(bytes: &'a [u8]) -> Result<Self, Error> {
+        let mut lines = BufReader::new(GzDecoder::new(bytes)).lines();
+        let first = lines.next().ok_or("archive is empty")??;
+        let Record::Header(header) = serde_json::from_str(&first)? else {
+            return Err("archive does not start with a header".into());
+        };
+        if header.version != FORMAT_VERSION {
+            return Err(format!("unsupported archive version {}", header.version).into());
+        }
+        Ok(Self { header, lines })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    // 메세지를 넣고 마지막에 sync range를 병합. range는 메세지가 다 들어간 뒤에야 믿을 수 있음
+    // 보관 기간이 지난 메세지는 넣지 않고 range도 보관 기간 경계부터로 줄임
+    pub async fn import(self, pool: &SqlitePool) -> Result<ImportSummary, Error> {
+        let guild = self.header.guild_id as i64;
+        let channel = self.header.channel_id as i64;
+        let mut summary = ImportSummary::default();
+        let mut ranges = Vec::new();
+        let mut page = Vec::new();
+        let redactor = database::load_redactor(pool, guild).await?;
+        let cutoff_id = database::effective_retention(
+            pool,
+            GuildId::new(self.header.guild_id),
+            ChannelId::new(self.header.channel_id),
+        )
+        .await?
+        .age_cutoff_id(chrono::Utc::now().timestamp())
+        .unwrap_or(0);
+
+        for line in self.lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line)? {
+                Record::Header(_) => return Err("archive has more than one header".into()),
+                Record::Range { start, end } => {
+                    ranges.extend(database::Range::new(start, end).clamp_start(cutoff_id))
+                }
+                Record::Message(msg) => {
+                    summary.messages += 1;
+                    if msg.message_id >= cutoff_id {
+                        page.push(msg);
+                    }
+                }
+            }
+            if page.len() as i64 >= PAGE_SIZE {
+                summary.inserted += insert_page(pool, guild, channel, &page, &redactor).await?;
+                page.clear();
+            }
+        }
+        summary.inserted += insert_page(pool, guild, channel, &page, &redactor).await?;
+
+        let mut tx = pool.begin().await?;
+        database::add_sync_ranges_in(&mut tx, channel, &ranges).await?;
+        tx.commit().await?;
+        summary.ranges = ranges.len();
+
+        Ok(summary)
+    }
+}
+
+async fn insert_page(
+    pool: &SqlitePool,
+    guild_id: i64,
+    channel_id: i64,
+    page: &[database::ArchivedMessage],
+    redactor: &Redactor,
+) -> Result<u64, Error> {
+    let mut tx = pool.begin().await?;
+    let inserted =
+        database::insert_archived_messages_in(&mut tx, guild_id, channel_id, page, redactor)
+            .await?;
+    tx.commit().await?;
+    Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert_row(pool: &SqlitePool, message_id: i64, content: &str) {
+        sqlx::query(
+            "INSERT INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at, reply_to_id)
+             VALUES (?, 10, 100, 1, 'user', ?, 0, NULL)",
+        )
+        .bind(message_id)
+        .bind(content)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn contents(pool: &SqlitePool) -> Vec<(i64, String)> {
+        sqlx::query_as("SELECT message_id, content FROM messages ORDER BY message_id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    // 보관 기간 안에 들어가는 id
+    fn recent_id(id: i64) -> i64 {
+        database::snowflake_from_timestamp(chrono::Utc::now().timestamp()) + id
+    }
+
+    #[tokio::test]
+    async fn round_trip_keeps_messages_and_ranges() {
+        let base = recent_id(0);
+        let source = database::memory_pool().await;
+        for id in 1..=2_500 {
+            insert_row(&source, base + id, &format!("메세지 {id}")).await;
+        }
+        database::add_sync_range(&source, 10, base + 1, base + 2_000)
+            .await
+            .unwrap();
+        database::add_sync_range(&source, 10, base + 2_100, base + 2_500)
+            .await
+            .unwrap();
+
+        let bytes = export(&source, GuildId::new(100), ChannelId::new(10), usize::MAX)
+            .await
+            .unwrap();
+
+        let target = database::memory_pool().await;
+        // 이미 있는 메세지는 덮어쓰지 않음
+        insert_row(&target, base + 5, "수정된 내용").await;
+        database::add_sync_range(&target, 10, base + 2_000, base + 2_100)
+            .await
+            .unwrap();
+
+        let archive = Archive::open(&bytes).unwrap();
+        assert_eq!(archive.header().channel_id, 10);
+        assert_eq!(archive.header().guild_id, 100);
+        let summary = archive.import(&target).await.unwrap();
+
+        assert_eq!(
+            summary,
+            ImportSummary {
+                messages: 2_500,
+                inserted: 2_499,
+                ranges: 2,
+            }
+        );
+        let imported = contents(&target).await;
+        assert_eq!(imported.len(), 2_500);
+        assert_eq!(imported[4], (base + 5, "수정된 내용".to_owned()));
+        assert_eq!(imported[0], (base + 1, "메세지 1".to_owned()));
+        assert_eq!(
+            database::list_sync_ranges(&target, 10).await.unwrap(),
+            vec![database::Range::new(base + 1, base + 2_500)]
+        );
+    }
+
+    #[tokio::test]
+    async fn import_skips_expired_messages() {
+        let recent = recent_id(1);
+        let source = database::memory_pool().await;
+        insert_row(&source, 1, "오래된 메세지").await;
+        insert_row(&source, recent, "최근 메세지").await;
+        database::add_sync_range(&source, 10, 1, recent)
+            .await
+            .unwrap();
+        let bytes = export(&source, GuildId::new(100), ChannelId::new(10), usize::MAX)
+            .await
+            .unwrap();
+
+        let target = database::memory_pool().await;
+        let summary = Archive::open(&bytes)
+            .unwrap()
+            .import(&target)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.messages, 2);
+        assert_eq!(summary.inserted, 1);
+        assert_eq!(contents(&target).await.len(), 1);
+        let ranges = database::list_sync_ranges(&target, 10).await.unwrap();
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0].start > 1);
+        assert_eq!(ranges[0].end, recent);
+    }
+
+    #[tokio::test]
+    async fn export_stops_when_too_large() {
+        let pool = database::memory_pool().await;
+        insert_row(&pool, 1, "hello").await;
+        assert!(
+            export(&pool, GuildId::new(100), ChannelId::new(10), 10)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn open_rejects_unknown_version() {
+        let mut out = GzEncoder::new(Vec::new(), Compression::default());
+        out.write_all(
+            br#"{"type":"header","version":99,"guild_id":1,"channel_id":2,"exported_at":0}"#,
+        )
+        .unwrap();
+        let bytes = out.finish().unwrap();
+        assert!(Archive::open(&bytes).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::settings::Settings;
+use crate::{Error, database};
+use poise::serenity_prelude::{ChannelId, GetMessages, GuildId, Http, MessageId};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+const BACKFILL_PAGE_SIZE: u8 = 100; // discord api limit
+
+// 캐싱 켜진 채널의 과거 메세지를 최신부터 거꾸로 한 페이지씩 채움
+// tick 당 api 호출 한 번이라 interval로 속도 조절
+pub async fn run(pool: SqlitePool, http: Arc<Http>, settings: Settings, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let state = match database::next_backfill_channel(&pool).await {
+            Ok(Some(state)) => state,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("next_backfill_channel failed: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = backfill_page(&pool, &http, &settings, &state).await {
+            tracing::warn!("backfill failed for channel {}: {}", state.channel_id, e);
+            // 실패한 채널이 계속 잡히지 않도록 순서만 뒤로 보냄
+            let _ = database::update_backfill_cursor(
+                &pool,
+                state.channel_id,
+                state.cursor_id,
+                state.status,
+            )
+            .await;
+        }
+    }
+}
+
+async fn backfill_page(
+    pool: &SqlitePool,
+    http: &Http,
+    settings: &Settings,
+    state: &database::BackfillState,
+) -> Result<(), Error> {
+    let channel_id = ChannelId::new(state.channel_id as u64);
+
+    // 검색이나 라이브 캐싱으로 이미 채워진 구간은 건너뜀
+    let mut cursor = state.cursor_id;
+    if let Some(cursor_id) = cursor
+        && let Some(range) = database::find_sync_range(pool, state.channel_id, cursor_id).await?
+    {
+        cursor = Some(range.start);
+    }
+
+    let mut request = GetMessages::new().limit(BACKFILL_PAGE_SIZE);
+    if let Some(cursor_id) = cursor {
+        request = request.before(MessageId::new(cursor_id as u64));
+    }
+
+    let mut messages = channel_id.messages(http, request).await?;
+
+    let (Some(newest), Some(oldest)) = (messages.first(), messages.last()) else {
+        return finish(pool, state, cursor).await;
+    };
+    let newest_id = newest.id.get() as i64;
+    let oldest_id = oldest.id.get() as i64;
+
+    // 보관 기간이 지난 메세지는 가져와봐야 정리 작업이 다시 지움
+    let guild_id = GuildId::new(state.guild_id as u64);
+    let policy = database::effective_retention(pool, guild_id, channel_id).await?;
+    let cutoff_id = policy
+        .age_cutoff_id(chrono::Utc::now().timestamp())
+        .filter(|cutoff_id| oldest_id < *cutoff_id);
+    if let Some(cutoff_id) = cutoff_id {
+        messages.retain(|m| m.id.get() as i64 >= cutoff_id);
+    }
+
+    let redactor = settings.redactor(guild_id).await?;
+    database::insert_messages(pool, &messages, state.guild_id, &redactor).await?;
+
+    // cursor 바로 앞부터 가져왔으니 cursor까지 연속된 구간
+    // 보관 기간 경계를 넘었으면 경계부터 cursor까지 빠짐없이 가져온 것
+    let min_id = cutoff_id.unwrap_or(oldest_id);
+    let max_id = cursor.unwrap_or(newest_id);
+    if min_id <= max_id {
+        database::add_sync_range(pool, state.channel_id, min_id, max_id).await?;
+    }
+
+    let count_reached = match policy.max_messages {
+        Some(max_messages) => {
+            database::count_channel_messages(pool, channel_id).await? >= max_messages
+        }
+        None => false,
+    };
+    if cutoff_id.is_some() || count_reached {
+        return finish(pool, state, Some(min_id)).await;
+    }
+
+    database::update_backfill_cursor(
+        pool,
+        state.channel_id,
+        Some(min_id),
+        database::BackfillStatus::Running,
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn finish(
+    pool: &SqlitePool,
+    state: &database::BackfillState,
+    cursor: Option<i64>,
+) -> Result<(), Error> {
+    tracing::info!("backfill done for channel {}", state.channel_id);
+    database::update_backfill_cursor(
+        pool,
+        state.channel_id,
+        cursor,
+        database::BackfillStatus::Done,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use crate::{Error, database};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// 백업 파일 이름. 시각 순서와 이름 순서가 같도록
+const FILE_PREFIX: &str = "discord_bot-";
+const FILE_EXTENSION: &str = ".db";
+
+// 봇이 쓰는 중인 DB 파일을 그대로 복사하면 깨질 수 있어서 VACUUM INTO로 백업
+pub async fn run(pool: SqlitePool, dir: PathBuf, keep: usize, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        let key = match database::database_encryption_key() {
+            Ok(key) => key,
+            Err(e) => {
+                tracing::warn!("Backup skipped, cannot read database key: {}", e);
+                continue;
+            }
+        };
+        match backup(&pool, &dir, keep, key.as_deref()).await {
+            Ok(path) => tracing::info!("Database backed up to {}", path.display()),
+            Err(e) => tracing::error!("Database backup failed: {}", e),
+        }
+    }
+}
+
+// dir에 새 백업을 만들고 키로 열어본 뒤, 최근 keep개만 남김
+pub async fn backup(
+    pool: &SqlitePool,
+    dir: &Path,
+    keep: usize,
+    key: Option<&str>,
+) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(dir)?;
+    let name = format!(
+        "{}{}{}",
+        FILE_PREFIX,
+        chrono::Utc::now().format("%Y%m%d-%H%M%S-%3f"),
+        FILE_EXTENSION
+    );
+    let path = dir.join(name);
+
+    database::backup_database(pool, &path).await?;
+    if let Err(e) = database::verify_database(&path, key).await {
+        let _ = std::fs::remove_file(&path);
+        return Err(format!("backup {} could not be verified: {}", path.display(), e).into());
+    }
+
+    for old in rotate(dir, keep)? {
+        tracing::info!("Removed old backup {}", old.display());
+    }
+    Ok(path)
+}
+
+// 지운 백업 경로를 반환. 백업이 아닌 파일은 건드리지 않음
+fn rotate(dir: &Path, keep: usize) -> Result<Vec<PathBuf>, Error> {
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_backup = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(FILE_PREFIX) && name.ends_with(FILE_EXTENSION));
+        if is_backup && path.is_file() {
+            backups.push(path);
+        }
+    }
+    backups.sort();
+
+    let removed = backups.len().saturating_sub(keep.max(1));
+    let removed: Vec<_> = backups.drain(..removed).collect();
+    for path in &removed {
+        std::fs::remove_file(path)?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqliteConnectOptions;
+
+    // in-memory DB에서 VACUUM INTO를 하면 파일이 아니라 메모리에 만들어짐
+    async fn file_pool(dir: &Path, key: Option<&str>) -> SqlitePool {
+        let mut options = SqliteConnectOptions::new()
+            .filename(dir.join("source.sqlite"))
+            .create_if_missing(true);
+        if let Some(key) = key {
+            options = options.pragma("key", format!("'{}'", key));
+        }
+        let pool = SqlitePool::connect_with(options).await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn backup_is_verified_and_rotated() {
+        let source = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let pool = file_pool(source.path(), None).await;
+        sqlx::query(
+            "INSERT INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at, reply_to_id)
+             VALUES (1, 10, 100, 1, 'user', '백업', 0, NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let unrelated = dir.path().join("notes.db");
+        std::fs::write(&unrelated, b"").unwrap();
+        for name in [
+            "discord_bot-20260101-000000-000.db",
+            "discord_bot-20260102-000000-000.db",
+        ] {
+            std::fs::write(dir.path().join(name), b"old").unwrap();
+        }
+
+        let path = backup(&pool, dir.path(), 2, None).await.unwrap();
+
+        let mut remaining: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            vec![
+                dir.path().join("discord_bot-20260102-000000-000.db"),
+                path.clone(),
+                unrelated,
+            ]
+        );
+
+        let backup_pool = sqlx::SqlitePool::connect(&format!("sqlite://{}", path.display()))
+            .await
+            .unwrap();
+        let content: String = sqlx::query_scalar("SELECT content FROM messages_fts")
+            .fetch_one(&backup_pool)
+            .await
+            .unwrap();
+        assert_eq!(content, "백업");
+    }
+
+    #[tokio::test]
+    async fn unverifiable_backup_is_removed() {
+        let source = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let pool = file_pool(source.path(), None).await;
+
+        // 평문 DB를 키로 열면 실패
+        assert!(backup(&pool, dir.path(), 3, Some("key")).await.is_err());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn encrypted_backup_stays_encrypted() {
+        let source = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let pool = file_pool(source.path(), Some("backup-key")).await;
+
+        let path = backup(&pool, dir.path(), 3, Some("backup-key"))
+            .await
+            .unwrap();
+
+        // VACUUM INTO가 원본과 같은 키로 암호화된 파일을 만들어야 함
+        database::verify_database(&path, Some("backup-key"))
+            .await
+            .unwrap();
+        assert!(database::verify_database(&path, None).await.is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::Error;
+use crate::settings::{ChannelScope, Settings};
+use poise::serenity_prelude::{
+    self as serenity, Channel, ChannelId, ChannelType, GuildChannel, GuildId,
+};
+
+const MAX_SCOPE_DEPTH: usize = 3; // thread -> 채널 -> category
+
+// 채널, (thread면) 부모 채널, category 순서로 캐싱 설정을 찾을 범위
+pub async fn channel_scope(
+    ctx: &serenity::Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+) -> ChannelScope {
+    let mut scope = ChannelScope {
+        guild_id,
+        channel_ids: Vec::new(),
+    };
+
+    let mut next = Some(channel_id);
+    while let Some(id) = next {
+        if scope.channel_ids.len() >= MAX_SCOPE_DEPTH {
+            break;
+        }
+        scope.channel_ids.push(id);
+
+        let Some(channel) = guild_channel(ctx, scope.guild_id, id).await else {
+            break;
+        };
+        scope.guild_id = Some(channel.guild_id);
+        next = channel.parent_id;
+    }
+
+    scope
+}
+
+pub async fn is_caching_enabled(
+    ctx: &serenity::Context,
+    settings: &Settings,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+) -> Result<bool, Error> {
+    let scope = channel_scope(ctx, guild_id, channel_id).await;
+    Ok(settings.is_caching_enabled(&scope).await?)
+}
+
+// 채널이 속한 category. thread면 부모 채널의 category
+pub async fn category_of(
+    ctx: &serenity::Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+) -> Option<ChannelId> {
+    let mut next = Some(channel_id);
+    for _ in 0..MAX_SCOPE_DEPTH {
+        let channel = guild_channel(ctx, guild_id, next?).await?;
+        if channel.kind == ChannelType::Category {
+            return Some(channel.id);
+        }
+        next = channel.parent_id;
+    }
+    None
+}
+
+// 메세지 이벤트마다 불리므로 캐시를 먼저 보고 없을 때만 api 호출
+pub async fn guild_channel(
+    ctx: &serenity::Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+) -> Option<GuildChannel> {
+    // thread는 채널 목록이 아니라 guild.threads에 있음
+    if let Some(guild_id) = guild_id
+        && let Some(guild) = ctx.cache.guild(guild_id)
+        && let Some(channel) = guild
+            .channels
+            .get(&channel_id)
+            .or_else(|| guild.threads.iter().find(|t| t.id == channel_id))
+    {
+        return Some(channel.clone());
+    }
+
+    match channel_id.to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) => Some(channel),
+        _ => None,
+    }
+}
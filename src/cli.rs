@@ -0,0 +1,163 @@
+use crate::{Error, backup, config, database, import, storage};
+use poise::serenity_prelude::{ChannelId, GuildId};
+use sqlx::SqlitePool;
+use std::path::Path;
+
+const USAGE: &str = "usage:
+  discord_search_bot [token]
+  discord_search_bot import <DiscordChatExporter .json | folder | data package folder>...
+  discord_search_bot encrypt
+  discord_search_bot decrypt <output .db>
+  discord_search_bot rekey <new key file>
+  discord_search_bot backup <folder>";
+
+// 봇을 띄우지 않고 DB만 다루는 명령. 첫 인자가 명령 이름이 아니면 None (토큰으로 보고 봇 실행)
+pub async fn run(args: &[String]) -> Option<Result<(), Error>> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        // 전부 SQLite 파일을 직접 다루는 명령
+        "import" | "encrypt" | "decrypt" | "rekey" | "backup"
+            if storage::is_postgres_url(&database::database_url()) =>
+        {
+            Err(format!("{command} requires a SQLite database, DATABASE_URL is PostgreSQL").into())
+        }
+        "import" => import(rest).await,
+        "encrypt" => encrypt(rest).await,
+        "decrypt" => decrypt(rest).await,
+        "rekey" => rekey(rest).await,
+        "backup" => backup(rest).await,
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+async fn import(paths: &[String]) -> Result<(), Error> {
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+    let pool = database::init_db().await?;
+
+    for path in paths {
+        for channel in import::import_path(&pool, Path::new(path)).await? {
+            println!(
+                "{}: channel {} (guild {}) {} messages, {} new{}",
+                path,
+                channel.channel_id,
+                channel.guild_id,
+                channel.messages,
+                channel.inserted,
+                match channel.range {
+                    Some(range) => format!(", synced {}..={}", range.start, range.end),
+                    None => String::new(),
+                }
+            );
+            warn_if_not_caching(&pool, channel.guild_id, channel.channel_id).await?;
+        }
+    }
+    Ok(())
+}
+
+fn encryption_key() -> Result<String, Error> {
+    database::database_encryption_key()?.ok_or_else(|| {
+        let paths: Vec<_> = config::get()
+            .database
+            .encryption_key_paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        format!("no database key file ({})", paths.join(" or ")).into()
+    })
+}
+
+// DATABASE_URL의 평문 DB를 키 파일로 암호화해서 봇이 여는 .sqlcipher 파일로 복사
+async fn encrypt(args: &[String]) -> Result<(), Error> {
+    if !args.is_empty() {
+        return Err(USAGE.into());
+    }
+    let key = encryption_key()?;
+    let plaintext = database::database_filename()?;
+    let encrypted = database::encrypted_database_filename(&plaintext);
+
+    database::encrypt_database(&plaintext, &encrypted, &key).await?;
+    println!(
+        "encrypted {} into {}; remove the plaintext file once the bot starts with it",
+        plaintext.display(),
+        encrypted.display()
+    );
+    Ok(())
+}
+
+async fn decrypt(args: &[String]) -> Result<(), Error> {
+    let [output] = args else {
+        return Err(USAGE.into());
+    };
+    let key = encryption_key()?;
+    let encrypted = database::encrypted_database_filename(&database::database_filename()?);
+
+    database::decrypt_database(&encrypted, &key, Path::new(output)).await?;
+    println!(
+        "decrypted {} into {}; this copy is not encrypted",
+        encrypted.display(),
+        output
+    );
+    Ok(())
+}
+
+// 키 파일은 읽기 전용으로 마운트될 수 있어서 직접 바꾸지 않음
+async fn rekey(args: &[String]) -> Result<(), Error> {
+    let [new_key_file] = args else {
+        return Err(USAGE.into());
+    };
+    let key = encryption_key()?;
+    let new_key = std::fs::read_to_string(new_key_file)?;
+    let encrypted = database::encrypted_database_filename(&database::database_filename()?);
+
+    database::rekey_database(&encrypted, &key, &new_key).await?;
+    println!(
+        "rekeyed {}; replace the db_key file with {} before starting the bot",
+        encrypted.display(),
+        new_key_file
+    );
+    Ok(())
+}
+
+// 봇이 실행 중이어도 됨. backup.keep(BACKUP_KEEP)개만 남기고 오래된 백업은 지움
+async fn backup(args: &[String]) -> Result<(), Error> {
+    let [dir] = args else {
+        return Err(USAGE.into());
+    };
+    let key = database::database_encryption_key()?;
+    let pool = database::init_db().await?;
+
+    let path = backup::backup(
+        &pool,
+        Path::new(dir),
+        config::get().backup.keep,
+        key.as_deref(),
+    )
+    .await?;
+    println!("backed up to {}", path.display());
+    Ok(())
+}
+
+// category 설정은 discord 없이 알 수 없어서 채널, 서버 설정만 봄
+async fn warn_if_not_caching(
+    pool: &SqlitePool,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<(), Error> {
+    let channel =
+        database::load_settings(pool, database::SettingScope::Channel(channel_id)).await?;
+    let guild = database::load_settings(pool, database::SettingScope::Guild(guild_id)).await?;
+    if channel.caching().or(guild.caching()) != Some(true) {
+        println!(
+            "  warning: caching is not enabled for channel {} here; search will not use it until `/config caching` is enabled",
+            channel_id
+        );
+    }
+    Ok(())
+}
@@ -3,8 +3,14 @@ mod help;
 mod config;
 mod version;
 mod notify;
+mod privacy;
+mod maintenance;
 
 use crate::{Data, Error};
+use poise::serenity_prelude::{self as serenity, ComponentInteraction};
+
+// 봇이 올릴 수 있는 첨부파일 크기 제한
+const MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
 
 pub fn commands() -> Vec<poise::Command<Data, Error>> {
     vec![
@@ -13,9 +19,19 @@ pub fn commands() -> Vec<poise::Command<Data, Error>> {
         config::config(),
         version::version(),
         notify::notify_version(),
+        privacy::privacy(),
+        maintenance::maintenance(),
     ]
 }
 
 pub async fn check_latest_version() -> Result<Option<String>, Error> {
     version::check_latest_version().await
 }
+
+pub async fn handle_component(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &ComponentInteraction,
+) -> Result<(), Error> {
+    search::handle_component(ctx, data, interaction).await
+}
@@ -1,43 +1,1084 @@
-use crate::{Context, Error};
+use super::MAX_ATTACHMENT_BYTES;
+use crate::database::{
+    self, BackfillStatus, BotCaching, BotFilter, CacheStats, RetentionPolicy, Setting,
+    SettingScope, SettingValue,
+};
+use crate::redaction::{self, Detector, RedactionRules, Redactor};
+use crate::{Context, Error, archive, caching, config};
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    Attachment, ChannelId, ChannelType, CreateAttachment, GuildChannel, GuildId, MessageId, User,
+};
+use sqlx::SqlitePool;
 
 /// 서치봇 설정을 관리합니다.
-#[poise::command(slash_command, subcommands("caching"), guild_only)]
+#[poise::command(
+    slash_command,
+    subcommands(
+        "caching",
+        "caching_reset",
+        "backfill",
+        "revisions",
+        "retention",
+        "bots",
+        "redaction",
+        "show",
+        "status",
+        "export",
+        "import"
+    ),
+    guild_only
+)]
 pub(super) async fn config(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// 이 채널에서 메세지 캐싱을 활성화 합니다. 서버 관리 권한 필요.
+#[derive(Debug, poise::ChoiceParameter)]
+pub(super) enum CachingScope {
+    #[name = "채널"]
+    Channel,
+    #[name = "카테고리"]
+    Category,
+    #[name = "서버"]
+    Server,
+}
+
+/// 메세지 캐싱을 채널, 카테고리, 서버 단위로 설정합니다. 서버 관리 권한 필요.
 #[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
 pub(super) async fn caching(
     ctx: Context<'_>,
     #[description = "Enable or disable caching"] enable: bool,
+    #[description = "설정 범위, 기본값은 채널"] scope: Option<CachingScope>,
+    #[description = "대상 채널이나 카테고리, 기본값은 현재 채널"] channel: Option<GuildChannel>,
 ) -> Result<(), Error> {
-    let Some(guild_channel) = ctx.guild_channel().await else {
+    let Some(guild_id) = ctx.guild_id() else {
         ctx.say("서버 내에서만 활성화 할 수 있는 옵션입니다.")
             .await?;
         return Ok(());
     };
-    
-    let pool = &ctx.data().database;
-    crate::database::set_channel_caching(pool, ctx.channel_id(), enable).await?;
+    let settings = &ctx.data().settings;
+    let target = channel.as_ref().map_or(ctx.channel_id(), |c| c.id);
+    let value = Some(SettingValue::Bool(enable));
+
+    let target_name = match scope.unwrap_or(CachingScope::Channel) {
+        CachingScope::Channel => {
+            settings
+                .set(
+                    guild_id,
+                    SettingScope::Channel(target),
+                    Setting::Caching,
+                    value,
+                )
+                .await?;
+            format!("채널 <#{}>", target)
+        }
+        CachingScope::Category => {
+            let category = match &channel {
+                Some(c) if c.kind == ChannelType::Category => Some(c.id),
+                _ => caching::category_of(ctx.serenity_context(), Some(guild_id), target).await,
+            };
+            let Some(category) = category else {
+                ctx.say("카테고리에 속한 채널이 아닙니다.").await?;
+                return Ok(());
+            };
+            settings
+                .set(
+                    guild_id,
+                    SettingScope::Channel(category),
+                    Setting::Caching,
+                    value,
+                )
+                .await?;
+            format!("카테고리 <#{}>", category)
+        }
+        CachingScope::Server => {
+            settings
+                .set(
+                    guild_id,
+                    SettingScope::Guild(guild_id),
+                    Setting::Caching,
+                    value,
+                )
+                .await?;
+            "서버 전체".to_owned()
+        }
+    };
+
+    let purged = sync_guild_caching(ctx, guild_id).await?;
+
+    let status = if enable { "활성화" } else { "비활성화" };
+    let mut reply = format!(
+        "{}에 의해 {}에서 메세지 캐싱이 **{}** 되었습니다.",
+        ctx.author().display_name(),
+        target_name,
+        status
+    );
+    if purged > 0 {
+        reply += &format!(
+            "\n캐싱이 꺼진 채널 {}개의 저장된 데이터가 삭제되었습니다.",
+            purged
+        );
+    }
+    reply += &format!("\n\n{}", describe_caching(ctx, guild_id).await?);
+    ctx.say(reply).await?;
+
+    Ok(())
+}
+
+/// 채널이나 카테고리의 개별 캐싱 설정을 지우고 상위 설정을 따르게 합니다. 서버 관리 권한 필요.
+#[poise::command(
+    slash_command,
+    rename = "caching-reset",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(super) async fn caching_reset(
+    ctx: Context<'_>,
+    #[description = "대상 채널이나 카테고리, 기본값은 현재 채널"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let target = channel.map_or(ctx.channel_id(), |c| c.id);
+
+    ctx.data()
+        .settings
+        .set(
+            guild_id,
+            SettingScope::Channel(target),
+            Setting::Caching,
+            None,
+        )
+        .await?;
+    let purged = sync_guild_caching(ctx, guild_id).await?;
+
+    let mut reply = format!("<#{}>의 개별 캐싱 설정을 지웠습니다.", target);
+    if purged > 0 {
+        reply += &format!(
+            "\n캐싱이 꺼진 채널 {}개의 저장된 데이터가 삭제되었습니다.",
+            purged
+        );
+    }
+    reply += &format!("\n\n{}", describe_caching(ctx, guild_id).await?);
+    ctx.say(reply).await?;
+
+    Ok(())
+}
+
+// 설정이 바뀐 뒤 서버 채널들의 캐싱 상태를 맞춤
+// 꺼진 채널의 데이터는 지우고 켜진 채널은 백필 대상에 추가. 지운 채널 수를 반환
+async fn sync_guild_caching(ctx: Context<'_>, guild_id: GuildId) -> Result<usize, Error> {
+    let storage = &ctx.data().storage;
+    let settings = &ctx.data().settings;
+    let serenity_ctx = ctx.serenity_context();
+
+    let mut purged = 0;
+    for channel_id in storage.list_guild_cached_channels(guild_id).await? {
+        if !caching::is_caching_enabled(serenity_ctx, settings, Some(guild_id), channel_id).await? {
+            storage.purge_channel(channel_id).await?;
+            ctx.data().live_ranges.remove(&channel_id);
+            purged += 1;
+        }
+    }
+
+    // 백필은 SQLite 저장소에서만 됨
+    let Some(pool) = storage.sqlite() else {
+        return Ok(purged);
+    };
+    for (channel_id, channel) in guild_id.channels(ctx).await? {
+        if !matches!(channel.kind, ChannelType::Text | ChannelType::News) {
+            continue;
+        }
+        if caching::is_caching_enabled(serenity_ctx, settings, Some(guild_id), channel_id).await? {
+            database::enable_backfill(pool, guild_id, channel_id).await?;
+        }
+    }
+
+    Ok(purged)
+}
+
+async fn describe_caching(ctx: Context<'_>, guild_id: GuildId) -> Result<String, Error> {
+    let channels = guild_id.channels(ctx).await?;
+    let ids: Vec<ChannelId> = channels.keys().copied().collect();
+
+    let server = match ctx
+        .data()
+        .settings
+        .get(SettingScope::Guild(guild_id))
+        .await?
+        .caching()
+    {
+        Some(true) => "켜짐",
+        _ => "꺼짐",
+    };
+
+    let mut categories = Vec::new();
+    let mut included = Vec::new();
+    let mut excluded = Vec::new();
+    for (id, settings) in ctx
+        .data()
+        .storage
+        .list_channel_settings(&ids, Setting::Caching)
+        .await?
+    {
+        let enabled = settings.caching() == Some(true);
+        let is_category = channels
+            .get(&id)
+            .is_some_and(|c| c.kind == ChannelType::Category);
+        let mention = format!("<#{}>", id);
+        match (is_category, enabled) {
+            (true, true) => categories.push(format!("{} 켜짐", mention)),
+            (true, false) => categories.push(format!("{} 꺼짐", mention)),
+            (false, true) => included.push(mention),
+            (false, false) => excluded.push(mention),
+        }
+    }
+
+    let list = |items: Vec<String>| {
+        if items.is_empty() {
+            "없음".to_owned()
+        } else {
+            items.join(", ")
+        }
+    };
+    Ok(format!(
+        "캐싱 설정\n서버: **{}**\n카테고리: {}\n포함 채널: {}\n제외 채널: {}\n채널 설정이 카테고리, 카테고리 설정이 서버 설정보다 우선합니다.",
+        server,
+        list(categories),
+        list(included),
+        list(excluded),
+    ))
+}
+
+/// 캐싱 채널의 과거 메세지 백필을 관리합니다. 서버 관리 권한 필요.
+#[poise::command(
+    slash_command,
+    subcommands("backfill_status", "backfill_pause", "backfill_resume"),
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(super) async fn backfill(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// 이 채널의 백필 진행 상황을 확인합니다.
+#[poise::command(
+    slash_command,
+    rename = "status",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(super) async fn backfill_status(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(pool) = sqlite_pool(ctx).await? else {
+        return Ok(());
+    };
+    let Some(state) = database::get_backfill_state(pool, ctx.channel_id()).await? else {
+        ctx.say("이 채널은 캐싱이 꺼져 있어 백필 대상이 아닙니다.")
+            .await?;
+        return Ok(());
+    };
+
+    let status = match state.status {
+        BackfillStatus::Running => "진행 중",
+        BackfillStatus::Paused => "일시 정지",
+        BackfillStatus::Done => "완료",
+    };
+    let progress = match state.cursor_id {
+        Some(cursor_id) => format!(
+            "<t:{}:f> 까지",
+            MessageId::new(cursor_id as u64)
+                .created_at()
+                .unix_timestamp()
+        ),
+        None => "시작 전".to_owned(),
+    };
+    let count = database::count_channel_messages(pool, ctx.channel_id()).await?;
+
+    ctx.say(format!(
+        "백필 상태: **{}**\n진행: {}\n캐시된 메세지: {}개",
+        status, progress, count
+    ))
+    .await?;
+    Ok(())
+}
+
+/// 이 채널의 백필을 일시 정지합니다.
+#[poise::command(slash_command, rename = "pause", required_permissions = "MANAGE_GUILD")]
+pub(super) async fn backfill_pause(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(pool) = sqlite_pool(ctx).await? else {
+        return Ok(());
+    };
+    let paused =
+        database::set_backfill_status(pool, ctx.channel_id(), BackfillStatus::Paused).await?;
+
+    if paused {
+        ctx.say("백필을 일시 정지했습니다.").await?;
+    } else {
+        ctx.say("이 채널은 캐싱이 꺼져 있어 백필 대상이 아닙니다.")
+            .await?;
+    }
+    Ok(())
+}
+
+/// 이 채널의 백필을 재개합니다.
+#[poise::command(
+    slash_command,
+    rename = "resume",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(super) async fn backfill_resume(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(pool) = sqlite_pool(ctx).await? else {
+        return Ok(());
+    };
+    if !caching::is_caching_enabled(
+        ctx.serenity_context(),
+        &ctx.data().settings,
+        ctx.guild_id(),
+        ctx.channel_id(),
+    )
+    .await?
+    {
+        ctx.say("이 채널은 캐싱이 꺼져 있어 백필 대상이 아닙니다.")
+            .await?;
+        return Ok(());
+    }
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    // 이 기능 이전에 캐싱을 켠 채널은 상태가 없음
+    database::enable_backfill(pool, guild_id, ctx.channel_id()).await?;
+    database::set_backfill_status(pool, ctx.channel_id(), BackfillStatus::Running).await?;
+
+    ctx.say("백필을 재개했습니다.").await?;
+    Ok(())
+}
+
+/// 이 서버에서 메세지 수정 기록 보관을 설정합니다. 서버 관리 권한 필요.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub(super) async fn revisions(
+    ctx: Context<'_>,
+    #[description = "Enable or disable edit history"] enable: bool,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    ctx.data()
+        .settings
+        .set(
+            guild_id,
+            SettingScope::Guild(guild_id),
+            Setting::Revisions,
+            Some(SettingValue::Bool(enable)),
+        )
+        .await?;
 
     if !enable {
-        crate::database::delete_channel_messages(pool, ctx.channel_id()).await?;
-        crate::database::delete_channel_sync_ranges(pool, ctx.channel_id()).await?;
+        ctx.data().storage.delete_guild_revisions(guild_id).await?;
     }
 
     let status = if enable {
         "활성화"
     } else {
-        "비활성화 (저장된 데이터 삭제됨)"
+        "비활성화 (저장된 수정 기록 삭제됨)"
     };
     ctx.say(format!(
-        "{}에 의해 채널 `<#{}>`에서 메세지 캐싱이 **{}** 되었습니다.",
+        "{}에 의해 이 서버의 메세지 수정 기록 보관이 **{}** 되었습니다.",
         ctx.author().display_name(),
-        guild_channel.name(),
         status
     ))
     .await?;
 
     Ok(())
 }
+
+/// 캐시 보관 기간과 메세지 개수 제한을 설정합니다. 서버 관리 권한 필요.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub(super) async fn retention(
+    ctx: Context<'_>,
+    #[description = "보관 기간(일), 0이면 설정을 지우고 서버 설정이나 기본값을 따름"] max_age_days: Option<u32>,
+    #[description = "채널당 보관할 메세지 수, 0이면 제한 해제"] max_messages: Option<u32>,
+    #[description = "이 채널 대신 서버 기본값으로 설정"] server: Option<bool>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let max_age = config::get().retention.max_age_days;
+    if let Some(days) = max_age_days
+        && i64::from(days) > max_age
+    {
+        ctx.say(format!(
+            "보관 기간은 최대 {}일까지 설정할 수 있습니다.",
+            max_age
+        ))
+        .await?;
+        return Ok(());
+    }
+    let settings = &ctx.data().settings;
+    let scope = if server.unwrap_or(false) {
+        SettingScope::Guild(guild_id)
+    } else {
+        SettingScope::Channel(ctx.channel_id())
+    };
+    settings
+        .set_retention(
+            guild_id,
+            scope,
+            max_age_days.map(i64::from),
+            max_messages.map(i64::from),
+        )
+        .await?;
+
+    let (channel, guild) = settings.retention(guild_id, ctx.channel_id()).await?;
+    ctx.say(format!(
+        "보관 정책\n{}\n기간이 지난 메세지는 주기적으로 삭제됩니다.",
+        describe_retention_policies(channel, guild)
+    ))
+    .await?;
+
+    Ok(())
+}
+
+fn describe_retention_policies(channel: RetentionPolicy, guild: RetentionPolicy) -> String {
+    format!(
+        "채널: {}\n서버: {}\n적용: **{}**",
+        describe_retention(channel),
+        describe_retention(guild),
+        describe_retention(channel.effective(guild)),
+    )
+}
+
+fn describe_retention(policy: RetentionPolicy) -> String {
+    if policy.is_empty() {
+        return "제한 없음".to_owned();
+    }
+    let mut parts = Vec::new();
+    if let Some(days) = policy.max_age_days {
+        parts.push(format!("{}일", days));
+    }
+    if let Some(count) = policy.max_messages {
+        parts.push(format!("{}개", count));
+    }
+    parts.join(", ")
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub(super) enum BotMode {
+    #[name = "사람 메세지만"]
+    Humans,
+    #[name = "모든 봇과 웹훅"]
+    All,
+    #[name = "웹훅만"]
+    Webhooks,
+}
+
+/// 이 채널에서 봇, 웹훅 메세지 캐싱 여부를 설정합니다. 서버 관리 권한 필요.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub(super) async fn bots(
+    ctx: Context<'_>,
+    #[description = "캐싱할 메세지 종류"] mode: Option<BotMode>,
+    #[description = "모드와 상관없이 캐싱할 봇 추가"] allow: Option<User>,
+    #[description = "허용 목록에서 봇 제거"] disallow: Option<User>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let settings = &ctx.data().settings;
+    let mut filter = settings.bot_filter(ctx.channel_id()).await?;
+
+    if let Some(mode) = mode {
+        filter.mode = match mode {
+            BotMode::Humans => BotCaching::Humans,
+            BotMode::All => BotCaching::All,
+            BotMode::Webhooks => BotCaching::Webhooks,
+        };
+    }
+    if let Some(user) = allow
+        && !filter.allowlist.contains(&user.id.get())
+    {
+        filter.allowlist.push(user.id.get());
+    }
+    if let Some(user) = disallow {
+        filter.allowlist.retain(|id| *id != user.id.get());
+    }
+    settings
+        .set_bot_filter(guild_id, ctx.channel_id(), &filter)
+        .await?;
+
+    ctx.say(format!(
+        "{}\n이후 캐싱되는 메세지부터 적용됩니다.",
+        describe_bot_filter(&filter)
+    ))
+    .await?;
+
+    Ok(())
+}
+
+fn describe_bot_filter(filter: &BotFilter) -> String {
+    let mode = match filter.mode {
+        BotCaching::Humans => "사람 메세지만",
+        BotCaching::All => "모든 봇과 웹훅",
+        BotCaching::Webhooks => "웹훅만",
+    };
+    let allowlist = if filter.allowlist.is_empty() {
+        "없음".to_owned()
+    } else {
+        filter
+            .allowlist
+            .iter()
+            .map(|id| format!("<@{}>", id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!("봇 메세지 캐싱: **{}**\n허용된 봇: {}", mode, allowlist)
+}
+
+/// 캐시에 저장하기 전에 토큰, 이메일 등을 가리는 규칙을 관리합니다. 서버 관리 권한 필요.
+#[poise::command(
+    slash_command,
+    subcommands(
+        "redaction_show",
+        "redaction_detector",
+        "redaction_add",
+        "redaction_remove"
+    ),
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(super) async fn redaction(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub(super) enum RedactionDetector {
+    #[name = "토큰"]
+    Tokens,
+    #[name = "API 키"]
+    ApiKeys,
+    #[name = "이메일"]
+    Emails,
+    #[name = "전화번호"]
+    Phones,
+}
+
+impl From<RedactionDetector> for Detector {
+    fn from(detector: RedactionDetector) -> Self {
+        match detector {
+            RedactionDetector::Tokens => Detector::Tokens,
+            RedactionDetector::ApiKeys => Detector::ApiKeys,
+            RedactionDetector::Emails => Detector::Emails,
+            RedactionDetector::Phones => Detector::Phones,
+        }
+    }
+}
+
+/// 이 서버의 가리기 규칙을 보여줍니다.
+#[poise::command(slash_command, rename = "show", required_permissions = "MANAGE_GUILD")]
+pub(super) async fn redaction_show(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let rules = ctx.data().settings.redaction(guild_id).await?;
+    ctx.send(
+        CreateReply::default()
+            .content(describe_redaction(&rules))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// 기본 감지기를 켜거나 끕니다.
+#[poise::command(
+    slash_command,
+    rename = "detector",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(super) async fn redaction_detector(
+    ctx: Context<'_>,
+    #[description = "감지할 종류"] detector: RedactionDetector,
+    #[description = "켜기 또는 끄기"] enable: bool,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let settings = &ctx.data().settings;
+    let detector = Detector::from(detector);
+    let mut rules = settings.redaction(guild_id).await?;
+
+    rules.detectors.retain(|d| *d != detector);
+    if enable {
+        rules.detectors.push(detector);
+    }
+    settings.set_redaction(guild_id, &rules).await?;
+
+    let added = RedactionRules {
+        detectors: vec![detector],
+        patterns: vec![],
+    };
+    apply_redaction(ctx, guild_id, &rules, enable.then_some(&added)).await
+}
+
+/// 가릴 내용을 정규식으로 추가합니다.
+#[poise::command(slash_command, rename = "add", required_permissions = "MANAGE_GUILD")]
+pub(super) async fn redaction_add(
+    ctx: Context<'_>,
+    #[description = "가릴 내용의 정규식"] pattern: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    if let Err(e) = redaction::compile_pattern(&pattern) {
+        ctx.say(e).await?;
+        return Ok(());
+    }
+    let settings = &ctx.data().settings;
+    let mut rules = settings.redaction(guild_id).await?;
+    if rules.patterns.contains(&pattern) {
+        ctx.say("이미 추가된 정규식입니다.").await?;
+        return Ok(());
+    }
+    if rules.patterns.len() >= redaction::MAX_PATTERNS {
+        ctx.say(format!(
+            "정규식은 {}개까지 추가할 수 있습니다.",
+            redaction::MAX_PATTERNS
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    rules.patterns.push(pattern.clone());
+    settings.set_redaction(guild_id, &rules).await?;
+
+    let added = RedactionRules {
+        detectors: vec![],
+        patterns: vec![pattern],
+    };
+    apply_redaction(ctx, guild_id, &rules, Some(&added)).await
+}
+
+/// 추가한 정규식을 제거합니다.
+#[poise::command(
+    slash_command,
+    rename = "remove",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(super) async fn redaction_remove(
+    ctx: Context<'_>,
+    #[description = "제거할 정규식"] pattern: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let settings = &ctx.data().settings;
+    let mut rules = settings.redaction(guild_id).await?;
+    let before = rules.patterns.len();
+    rules.patterns.retain(|p| *p != pattern);
+    if rules.patterns.len() == before {
+        ctx.say("추가된 적 없는 정규식입니다.").await?;
+        return Ok(());
+    }
+    settings.set_redaction(guild_id, &rules).await?;
+    apply_redaction(ctx, guild_id, &rules, None).await
+}
+
+// 새로 추가된 규칙만 이미 캐시된 메세지에 적용. 전체 규칙을 다시 돌리면 사용자 정규식이
+// 가린 자리의 표시에 또 매치될 수 있음. 이미 가려진 내용은 규칙을 지워도 돌아오지 않음
+async fn apply_redaction(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+    rules: &RedactionRules,
+    added: Option<&RedactionRules>,
+) -> Result<(), Error> {
+    let mut message = describe_redaction(rules);
+    if let Some(added) = added {
+        ctx.defer_ephemeral().await?;
+        let redactor = Redactor::new(added);
+        let changed = ctx
+            .data()
+            .storage
+            .redact_guild_messages(guild_id, &redactor)
+            .await?;
+        message.push_str(&format!(
+            "\n이미 캐시된 메세지와 수정 기록 {}개에도 적용했습니다.",
+            changed
+        ));
+    } else {
+        message.push_str("\n이미 가려진 내용은 복구되지 않습니다.");
+    }
+    // 정규식 목록이 채널에 보이지 않도록 본인에게만
+    ctx.send(CreateReply::default().content(message).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+fn describe_redaction(rules: &RedactionRules) -> String {
+    let patterns = if rules.patterns.is_empty() {
+        "없음".to_owned()
+    } else {
+        rules
+            .patterns
+            .iter()
+            .map(|p| format!("`{}`", p.replace('`', "\\`")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "가리기 감지기: **{}**\n가리기 정규식: {}",
+        describe_detectors(rules),
+        patterns
+    )
+}
+
+// 누구나 볼 수 있는 /config show용. 정규식 내용은 빼고 개수만
+fn describe_redaction_summary(rules: &RedactionRules) -> String {
+    format!(
+        "가리기 감지기: **{}**\n가리기 정규식: **{}개**",
+        describe_detectors(rules),
+        rules.patterns.len()
+    )
+}
+
+fn describe_detectors(rules: &RedactionRules) -> String {
+    if rules.detectors.is_empty() {
+        "없음".to_owned()
+    } else {
+        Detector::ALL
+            .into_iter()
+            .filter(|d| rules.detectors.contains(d))
+            .map(|d| match d {
+                Detector::Tokens => "토큰",
+                Detector::ApiKeys => "API 키",
+                Detector::Emails => "이메일",
+                Detector::Phones => "전화번호",
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// 이 채널에 적용되는 설정을 모두 보여줍니다.
+#[poise::command(slash_command)]
+pub(super) async fn show(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let settings = &ctx.data().settings;
+    let channel_id = ctx.channel_id();
+
+    let scope = caching::channel_scope(ctx.serenity_context(), Some(guild_id), channel_id).await;
+    let caching = match settings.resolve_caching(&scope).await? {
+        Some((source, enabled)) => {
+            let source = match source {
+                SettingScope::Guild(_) => "서버 설정".to_owned(),
+                SettingScope::Channel(id) if id == channel_id => "채널 설정".to_owned(),
+                SettingScope::Channel(id) => format!("<#{}> 설정", id),
+            };
+            let status = if enabled { "켜짐" } else { "꺼짐" };
+            format!("**{}** ({})", status, source)
+        }
+        None => "**꺼짐** (기본값)".to_owned(),
+    };
+    let revisions = if settings.is_revisions_enabled(guild_id).await? {
+        "켜짐"
+    } else {
+        "꺼짐"
+    };
+    let (channel, guild) = settings.retention(guild_id, channel_id).await?;
+    let bots = settings.bot_filter(channel_id).await?;
+    let redaction = settings.redaction(guild_id).await?;
+
+    ctx.say(format!(
+        "<#{}> 설정\n캐싱: {}\n수정 기록 보관: **{}**\n\n보관 정책\n{}\n\n{}\n\n{}",
+        channel_id,
+        caching,
+        revisions,
+        describe_retention_policies(channel, guild),
+        describe_bot_filter(&bots),
+        describe_redaction_summary(&redaction),
+    ))
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub(super) enum StatusScope {
+    #[name = "채널"]
+    Channel,
+    #[name = "서버"]
+    Server,
+}
+
+const MAX_STATUS_GAPS: usize = 10; // 채널 상태에 보여줄 빠진 구간 수
+const MAX_STATUS_CHANNELS: usize = 20; // 서버 상태에 보여줄 채널 수
+
+/// 캐시된 메세지 수, 기간, 빠진 구간, DB 크기를 확인합니다. 서버 관리 권한 필요.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub(super) async fn status(
+    ctx: Context<'_>,
+    #[description = "확인할 범위, 기본값은 채널"] scope: Option<StatusScope>,
+    #[description = "확인할 채널, 기본값은 현재 채널"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    ctx.defer_ephemeral().await?;
+
+    let status = match scope.unwrap_or(StatusScope::Channel) {
+        StatusScope::Channel => {
+            channel_status(ctx, channel.map_or(ctx.channel_id(), |c| c.id)).await?
+        }
+        StatusScope::Server => guild_status(ctx, guild_id).await?,
+    };
+    let size = ctx.data().storage.database_size().await?;
+
+    ctx.say(format!("{}\n\nDB 크기: {}", status, describe_size(size)))
+        .await?;
+    Ok(())
+}
+
+async fn channel_status(ctx: Context<'_>, channel_id: ChannelId) -> Result<String, Error> {
+    let storage = &ctx.data().storage;
+    let stats = storage.channel_cache_stats(channel_id).await?;
+    let ranges = storage.list_sync_ranges(channel_id.get() as i64).await?;
+    let gaps = database::sync_gaps(&ranges);
+
+    let live = match ctx.data().live_ranges.get(&channel_id) {
+        Some(range) => format!("**진행 중** ({} 부터)", describe_id_time(range.start, 'f')),
+        None => "**없음**".to_owned(),
+    };
+    let mut gap_lines: Vec<_> = gaps
+        .iter()
+        .take(MAX_STATUS_GAPS)
+        .map(|gap| {
+            format!(
+                "- {} ~ {}",
+                describe_id_time(gap.start, 'f'),
+                describe_id_time(gap.end, 'f')
+            )
+        })
+        .collect();
+    if gaps.len() > MAX_STATUS_GAPS {
+        gap_lines.push(format!("- 외 {}개", gaps.len() - MAX_STATUS_GAPS));
+    }
+
+    let mut status = format!(
+        "<#{}> 캐시 상태\n캐시된 메세지: {}개\n기간: {}\n실시간 캐싱 구간: {}\n빠진 구간: {}개",
+        channel_id,
+        stats.count,
+        describe_period(&stats, 'f'),
+        live,
+        gaps.len()
+    );
+    for line in gap_lines {
+        status.push('\n');
+        status.push_str(&line);
+    }
+    Ok(status)
+}
+
+async fn guild_status(ctx: Context<'_>, guild_id: GuildId) -> Result<String, Error> {
+    let storage = &ctx.data().storage;
+    let mut channels = Vec::new();
+    for channel_id in storage.list_guild_cached_channels(guild_id).await? {
+        let stats = storage.channel_cache_stats(channel_id).await?;
+        let ranges = storage.list_sync_ranges(channel_id.get() as i64).await?;
+        channels.push((channel_id, stats, database::sync_gaps(&ranges).len()));
+    }
+    if channels.is_empty() {
+        return Ok("이 서버에 캐시된 메세지가 없습니다.".to_owned());
+    }
+    channels.sort_by_key(|(_, stats, _)| std::cmp::Reverse(stats.count));
+
+    let total: i64 = channels.iter().map(|(_, stats, _)| stats.count).sum();
+    let live = channels
+        .iter()
+        .filter(|(channel_id, _, _)| ctx.data().live_ranges.contains_key(channel_id))
+        .count();
+    let mut status = format!(
+        "서버 캐시 상태\n캐시된 메세지: {}개 ({}개 채널)\n실시간 캐싱 중인 채널: {}개",
+        total,
+        channels.len(),
+        live
+    );
+    for (channel_id, stats, gaps) in channels.iter().take(MAX_STATUS_CHANNELS) {
+        let live = if ctx.data().live_ranges.contains_key(channel_id) {
+            ", 실시간"
+        } else {
+            ""
+        };
+        status.push_str(&format!(
+            "\n- <#{}>: {}개, {}, 빠진 구간 {}개{}",
+            channel_id,
+            stats.count,
+            describe_period(stats, 'd'),
+            gaps,
+            live
+        ));
+    }
+    if channels.len() > MAX_STATUS_CHANNELS {
+        status.push_str(&format!(
+            "\n- 외 {}개 채널",
+            channels.len() - MAX_STATUS_CHANNELS
+        ));
+    }
+    Ok(status)
+}
+
+// message id(snowflake)를 discord 시간 표시로
+fn describe_id_time(id: i64, style: char) -> String {
+    let time = MessageId::new(id.max(1) as u64)
+        .created_at()
+        .unix_timestamp();
+    format!("<t:{}:{}>", time, style)
+}
+
+fn describe_period(stats: &CacheStats, style: char) -> String {
+    match (stats.earliest, stats.latest) {
+        (Some(earliest), Some(latest)) => {
+            format!("<t:{}:{}> ~ <t:{}:{}>", earliest, style, latest, style)
+        }
+        _ => "없음".to_owned(),
+    }
+}
+
+fn describe_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+/// 채널의 캐시된 메세지를 압축된 JSONL 파일로 내보냅니다. 서버 관리 권한 필요.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub(super) async fn export(
+    ctx: Context<'_>,
+    #[description = "내보낼 채널, 기본값은 현재 채널"] channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let channel_id = channel.map_or(ctx.channel_id(), |c| c.id);
+    ctx.defer_ephemeral().await?;
+
+    let Some(pool) = sqlite_pool(ctx).await? else {
+        return Ok(());
+    };
+    let count = database::count_channel_messages(pool, channel_id).await?;
+    if count == 0 {
+        ctx.say(format!("<#{}>에 캐시된 메세지가 없습니다.", channel_id))
+            .await?;
+        return Ok(());
+    }
+
+    let bytes = match archive::export(pool, guild_id, channel_id, MAX_ATTACHMENT_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("export failed for {}: {}", channel_id, e);
+            ctx.say(format!(
+                "내보내기에 실패했습니다. 파일이 {}MB를 넘으면 첨부할 수 없습니다.",
+                MAX_ATTACHMENT_BYTES / (1024 * 1024)
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "<#{}>의 캐시된 메세지 {}개를 내보냈습니다.\n`/config import`로 다른 봇에 가져올 수 있습니다.",
+                channel_id, count
+            ))
+            .attachment(CreateAttachment::bytes(
+                bytes,
+                format!("{}.jsonl.gz", channel_id),
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// `/config export`로 내보낸 파일을 캐시로 가져옵니다. 서버 관리 권한 필요.
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub(super) async fn import(
+    ctx: Context<'_>,
+    #[description = "내보낸 .jsonl.gz 파일"] file: Attachment,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    if file.size as usize > MAX_ATTACHMENT_BYTES {
+        ctx.say("파일이 너무 큽니다.").await?;
+        return Ok(());
+    }
+    ctx.defer_ephemeral().await?;
+
+    let bytes = file.download().await?;
+    let archive = match archive::Archive::open(&bytes) {
+        Ok(archive) => archive,
+        Err(e) => {
+            ctx.say(format!("아카이브 파일을 읽을 수 없습니다: {}", e))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // 다른 서버의 메세지가 이 서버 검색에 섞이지 않도록 같은 서버의 아카이브만 받음
+    let header = archive.header();
+    if header.guild_id != guild_id.get() {
+        ctx.say("다른 서버에서 내보낸 파일입니다.").await?;
+        return Ok(());
+    }
+    // header는 파일에 적힌 값이라 그대로 믿지 않고 채널이 실제로 이 서버에 있는지 확인
+    let channel_id = ChannelId::new(header.channel_id);
+    let in_guild = caching::guild_channel(ctx.serenity_context(), Some(guild_id), channel_id)
+        .await
+        .is_some_and(|channel| channel.guild_id == guild_id);
+    if !in_guild {
+        ctx.say("이 서버에 없는 채널의 파일입니다.").await?;
+        return Ok(());
+    }
+    if !caching::is_caching_enabled(
+        ctx.serenity_context(),
+        &ctx.data().settings,
+        Some(guild_id),
+        channel_id,
+    )
+    .await?
+    {
+        ctx.say(format!(
+            "<#{}>은 캐싱이 꺼져 있습니다. 캐싱을 켠 뒤 가져와 주세요.",
+            channel_id
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let Some(pool) = sqlite_pool(ctx).await? else {
+        return Ok(());
+    };
+    let summary = archive.import(pool).await?;
+    ctx.say(format!(
+        "<#{}>에 메세지 {}개 중 {}개를 새로 가져왔습니다. 이미 캐시된 메세지는 그대로 둡니다.",
+        channel_id, summary.messages, summary.inserted
+    ))
+    .await?;
+    Ok(())
+}
+
+// 백필, 아카이브는 SQLite 저장소에서만 됨
+async fn sqlite_pool(ctx: Context<'_>) -> Result<Option<&SqlitePool>, Error> {
+    let pool = ctx.data().storage.sqlite();
+    if pool.is_none() {
+        ctx.say("PostgreSQL 저장소에서는 지원하지 않는 기능입니다.")
+            .await?;
+    }
+    Ok(pool)
+}
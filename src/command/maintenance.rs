@@ -0,0 +1,23 @@
+use crate::{Context, Error};
+use poise::CreateReply;
+
+/// DB 정리 작업을 바로 실행합니다. 봇 소유자 전용.
+#[poise::command(
+    slash_command,
+    owners_only,
+    hide_in_help,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub(super) async fn maintenance(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let message = match &ctx.data().maintenance {
+        Some(maintenance) => match maintenance.run_once().await {
+            Some(report) => format!("DB 정리 작업 결과\n```\n{}```", report),
+            None => "이미 DB 정리 작업이 실행 중입니다.".to_owned(),
+        },
+        None => "PostgreSQL 저장소에서는 지원하지 않는 기능입니다.".to_owned(),
+    };
+    ctx.send(CreateReply::default().content(message).ephemeral(true))
+        .await?;
+    Ok(())
+}
@@ -7,8 +7,10 @@ pub(super) async fn notify_version(
     ctx: Context<'_>,
     #[description = "Enable or disable update notification DM"] enable: bool,
 ) -> Result<(), Error> {
-    let pool = &ctx.data().database;
-    crate::database::set_version_subscription(pool, ctx.author().id.get(), enable).await?;
+    ctx.data()
+        .storage
+        .set_version_subscription(ctx.author().id.get(), enable)
+        .await?;
 
     let message = if enable {
         "새 버전 감지 시 DM 알림을 활성화했습니다."
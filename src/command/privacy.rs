@@ -0,0 +1,115 @@
+use super::MAX_ATTACHMENT_BYTES;
+use crate::{Context, Error};
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateAttachment, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+};
+use std::time::Duration;
+
+/// 봇이 저장한 내 데이터를 관리합니다.
+#[poise::command(slash_command, subcommands("forget_me", "my_data"))]
+pub(super) async fn privacy(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// 모든 서버에서 캐시된 내 메세지를 지우고, 이후 내 메세지를 캐싱하지 않습니다.
+#[poise::command(slash_command, rename = "forget-me")]
+pub(super) async fn forget_me(ctx: Context<'_>) -> Result<(), Error> {
+    let confirm_id = format!("forget-me:{}", ctx.id());
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .ephemeral(true)
+                .content(
+                    "봇이 모든 서버에서 캐싱한 내 메세지와 표시 이름을 삭제하고, 이후 내 메세지를 저장하지 않습니다.\n\
+                     검색 결과에는 내 메세지 내용 대신 안내 문구가 표시됩니다. 계속할까요?",
+                )
+                .components(vec![CreateActionRow::Buttons(vec![
+                    CreateButton::new(&confirm_id)
+                        .label("삭제")
+                        .style(ButtonStyle::Danger),
+                ])]),
+        )
+        .await?;
+
+    let Some(interaction) = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![confirm_id])
+        .timeout(Duration::from_secs(60))
+        .await
+    else {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content("취소되었습니다.")
+                    .components(vec![]),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    let deleted = ctx.data().storage.forget_user(ctx.author().id).await?;
+    tracing::info!("User {} opted out of caching", ctx.author().id);
+
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(format!(
+                        "캐시된 메세지 {}개를 삭제했습니다. 이후 내 메세지는 캐싱되지 않습니다.",
+                        deleted
+                    ))
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// 봇이 저장한 내 데이터를 JSON 파일로 DM 받습니다.
+#[poise::command(slash_command, rename = "my-data")]
+pub(super) async fn my_data(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let data = ctx
+        .data()
+        .storage
+        .collect_user_data(ctx.author().id)
+        .await?;
+    let count = data.messages.len();
+    let json = serde_json::to_vec_pretty(&data)?;
+    if json.len() > MAX_ATTACHMENT_BYTES {
+        ctx.say(format!(
+            "데이터가 {}MB를 넘어 DM으로 보낼 수 없습니다. 운영자에게 문의해 주세요.",
+            MAX_ATTACHMENT_BYTES / (1024 * 1024)
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let dm = ctx
+        .author()
+        .direct_message(
+            ctx,
+            CreateMessage::new()
+                .content(format!(
+                    "봇이 저장한 내 데이터입니다. 캐시된 메세지 {}개가 포함되어 있습니다.",
+                    count
+                ))
+                .add_file(CreateAttachment::bytes(
+                    json,
+                    format!("my-data-{}.json", ctx.author().id),
+                )),
+        )
+        .await;
+
+    let reply = match dm {
+        Ok(_) => "DM으로 내 데이터를 보냈습니다.",
+        Err(_) => "DM을 보낼 수 없습니다. 서버 멤버의 DM 허용 설정을 확인해 주세요.",
+    };
+    ctx.say(reply).await?;
+    Ok(())
+}
@@ -1,25 +1,28 @@
+mod conversation;
+mod history;
 mod logic;
 #[cfg(test)]
 mod tests;
 
+use crate::storage::Storage;
 use crate::{
-    Context, Error,
+    Context, Data, Error, caching, config,
     database::{self, SearchResult},
 };
-use logic::{substr, timestamp_to_readable};
+use logic::{OPTED_OUT_CONTENT, result_embed};
 use poise::CreateReply;
 use poise::serenity_prelude::{
-    self as serenity, ChannelId, CreateActionRow, CreateButton, CreateEmbed, CreateMessage,
-    EditMessage, GetMessages, GuildId, Message, MessageId,
+    self as serenity, ChannelId, ComponentInteraction, CreateActionRow, CreateButton,
+    CreateMessage, EditMessage, GetMessages, GuildId, Message, MessageId,
 };
-use sqlx::SqlitePool;
 use std::vec;
 
 const SEARCH_MESSAGE_LIMIT: usize = 100; // discord api limit
-const SEARCH_COUNT: usize = 10; // search 10 times, so search latest 1000 messages
-const DB_PAGE_SIZE: u32 = 10; // 메세지 하나에 최대 10개 결과 표시
 
-const END_OF_CHANNEL: &str = "채널에 모든 메세지를 검색했거나, 잦은 검색 호출로 discord api 호출 제한이 걸렸습니다!";
+const END_OF_CHANNEL: &str =
+    "채널에 모든 메세지를 검색했거나, 잦은 검색 호출로 discord api 호출 제한이 걸렸습니다!";
+
+const END_OF_RETENTION: &str = "보관 기간 안의 메세지를 모두 검색했습니다!";
 
 /// 메세지를 검색합니다
 #[poise::command(slash_command, prefix_command)]
@@ -27,14 +30,20 @@ pub(super) async fn search(
     ctx: Context<'_>,
     #[description = "검색할 단어"] text: String,
     #[description = "결과물을 찾을 때 까지 검색"] search_until_find: Option<bool>,
+    #[description = "수정 전 내용도 검색"] search_revisions: Option<bool>,
 ) -> Result<(), Error> {
-    let pool = &ctx.data().database;
     let search_until_find = search_until_find.unwrap_or(false);
+    let search_revisions = search_revisions.unwrap_or(false);
 
     let channel_to_search = ctx.channel_id();
-    let caching_enabled = database::is_channel_caching_enabled(pool, channel_to_search)
-        .await
-        .unwrap_or(false);
+    let caching_enabled = caching::is_caching_enabled(
+        ctx.serenity_context(),
+        &ctx.data().settings,
+        ctx.guild_id(),
+        channel_to_search,
+    )
+    .await
+    .unwrap_or(false);
 
     let guild_name = ctx
         .guild()
@@ -78,8 +87,8 @@ pub(super) async fn search(
         cache_search(
             ctx,
             text,
-            pool,
             search_until_find,
+            search_revisions,
             channel_to_search,
             dm,
             guild_id,
@@ -102,12 +111,27 @@ pub(super) async fn search(
 async fn cache_search(
     ctx: Context<'_>,
     text: String,
-    pool: &SqlitePool,
     search_until_find: bool,
+    search_revisions: bool,
     channel_to_search: ChannelId,
     dm: Message,
     guild_id: GuildId,
 ) -> Result<(), Error> {
+    let storage = &ctx.data().storage;
+    let include_revisions =
+        search_revisions && ctx.data().settings.is_revisions_enabled(guild_id).await?;
+
+    // 보관 기간이 지난 메세지는 api로 가져오지도, 저장하지도 않음
+    let (channel_policy, guild_policy) = ctx
+        .data()
+        .settings
+        .retention(guild_id, channel_to_search)
+        .await?;
+    let cutoff_id = channel_policy
+        .effective(guild_policy)
+        .age_cutoff_id(chrono::Utc::now().timestamp());
+    let redactor = ctx.data().settings.redactor(guild_id).await?;
+
     let mut current_range = match ctx.data().live_ranges.get(&channel_to_search) {
         Some(r) => *r,
         None => {
@@ -122,40 +146,62 @@ async fn cache_search(
     let mut search_cursor = current_range.end;
     loop {
         while {
-            let messages_from_db = database::search_messages_range(
-                pool,
-                guild_id.get() as i64,
-                channel_to_search.get() as i64,
-                &text,
-                current_range.start,
-                search_cursor,
-                DB_PAGE_SIZE,
-            )
-            .await?;
+            let messages_from_db = storage
+                .search_messages_range(
+                    guild_id.get() as i64,
+                    channel_to_search.get() as i64,
+                    &text,
+                    current_range.start,
+                    search_cursor,
+                    config::get().search.page_size,
+                    include_revisions,
+                )
+                .await?;
 
             if !messages_from_db.is_empty() {
                 send_search_results(&ctx, &dm, &messages_from_db).await?;
                 search_cursor = messages_from_db.last().unwrap().message_id - 1;
             } else {
                 // db에 있는 live range 다 긁어옴. 이제 api 호출로 db 채우고 live range 확장하고 루프 반복
+                if cutoff_id.is_some_and(|cutoff_id| current_range.start <= cutoff_id) {
+                    send_dm(ctx, END_OF_RETENTION).await?;
+                    return Ok(());
+                }
                 let before_id = MessageId::new(current_range.start as u64);
 
-                let messages =
-                    get_messages_from_discord_api(&ctx, channel_to_search, &dm, before_id).await?;
+                let mut messages = get_messages_from_discord_api(
+                    &ctx,
+                    channel_to_search,
+                    &dm,
+                    before_id,
+                    cutoff_id,
+                )
+                .await?;
 
                 if messages.is_empty() {
                     send_dm(ctx, END_OF_CHANNEL).await?;
                     return Ok(());
                 }
 
-                database::insert_messages(pool, &messages, guild_id.get() as i64).await?;
+                let oldest_id = messages.last().unwrap().id.get() as i64;
+                let newest_id = messages.first().unwrap().id.get() as i64;
+
+                // 보관 기간 경계를 넘었으면 경계부터 가져온 것까지 빠짐없이 가져온 것
+                let crossed_cutoff = cutoff_id.filter(|cutoff_id| oldest_id < *cutoff_id);
+                if let Some(cutoff_id) = crossed_cutoff {
+                    messages.retain(|m| m.id.get() as i64 >= cutoff_id);
+                }
+
+                storage
+                    .insert_messages(&messages, guild_id.get() as i64, &redactor)
+                    .await?;
 
-                let min_id = messages.last().unwrap().id.get() as i64;
-                let max_id = messages.first().unwrap().id.get() as i64;
+                let min_id = crossed_cutoff.unwrap_or(oldest_id);
+                let max_id = newest_id.max(min_id);
 
-                let extended_range =
-                    database::add_sync_range(pool, channel_to_search.get() as i64, min_id, max_id)
-                        .await?;
+                let extended_range = storage
+                    .add_sync_range(channel_to_search.get() as i64, min_id, max_id)
+                    .await?;
 
                 current_range = database::Range::new(extended_range.start, current_range.end);
 
@@ -186,7 +232,8 @@ async fn non_cache_search(
     loop {
         while {
             let messages =
-                get_messages_from_discord_api(&ctx, channel_to_search, &dm, last_msg_id).await?;
+                get_messages_from_discord_api(&ctx, channel_to_search, &dm, last_msg_id, None)
+                    .await?;
 
             if messages.is_empty() {
                 send_dm(ctx, END_OF_CHANNEL).await?;
@@ -218,14 +265,16 @@ async fn get_messages_from_discord_api(
     channel_to_search: ChannelId,
     dm: &Message,
     last_msg_id: MessageId,
+    until_id: Option<i64>,
 ) -> poise::serenity_prelude::Result<Vec<Message>> {
     // api 호출 느리니까 타이핑 인디케이터 ux
     let _typing = dm.channel_id.start_typing(&ctx.serenity_context().http);
     let mut oldest_message_id = last_msg_id;
 
-    // 1000개 긁어옴
-    let mut result = Vec::with_capacity(SEARCH_MESSAGE_LIMIT * SEARCH_COUNT);
-    for _ in 0..SEARCH_COUNT {
+    // 기본값이면 100개씩 10번, 1000개 긁어옴
+    let search_count = config::get().search.count;
+    let mut result = Vec::with_capacity(SEARCH_MESSAGE_LIMIT * search_count);
+    for _ in 0..search_count {
         // 참고 : 여기서 api 검색한 결과는 guild_id가 비워져서 올 수 있음
         let maybe_search_result = channel_to_search
             .messages(
@@ -239,7 +288,11 @@ async fn get_messages_from_discord_api(
         match maybe_search_result {
             Ok(search_result) if !search_result.is_empty() => {
                 oldest_message_id = search_result.last().unwrap().id;
-                result.extend(search_result.into_iter());
+                result.extend(search_result);
+                // until_id보다 오래된 메세지까지 왔으면 더 가져오지 않음
+                if until_id.is_some_and(|until_id| (oldest_message_id.get() as i64) < until_id) {
+                    break;
+                }
             }
             _ => break,
         }
@@ -255,25 +308,64 @@ async fn send_search_results(
 ) -> Result<(), Error> {
     // max size of discord embed field is 1024 (max embed size is 6000)
     // 10 is heuristic (msg(max 50) + author + time + etc... * 10 < 6000)
+    let results = hide_opted_out(ctx.data().storage.as_ref(), results.to_vec()).await?;
+    let ids = results.iter().map(|r| r.message_id).collect::<Vec<_>>();
+    let edited = ctx.data().storage.messages_with_revisions(&ids).await?;
+
     let chunks = results.chunks(10);
     for chunk in chunks {
         let mut msg_builder = CreateMessage::new();
-        for msg in chunk {
-            let timestamp =
-                serenity::Timestamp::from_unix_timestamp(msg.created_at).unwrap_or_default();
-            let title = format!(
-                "{}\t{}\t{}",
-                &msg.author_name,
-                &timestamp_to_readable(timestamp),
-                msg.link(),
-            );
-            let first_3_lines = msg.content.lines().take(3).collect::<Vec<_>>().join("\n");
-            let content = format!("{}", substr(&first_3_lines, 50));
+        let mut conversation_buttons = Vec::with_capacity(chunk.len());
+        let mut history_buttons = Vec::new();
+        for (i, msg) in chunk.iter().enumerate() {
             msg_builder = msg_builder
-                .add_embed(CreateEmbed::new().field(&title, &content, false))
+                .add_embed(result_embed(msg, &format!("{}. ", i + 1), 50))
                 .reference_message(dm);
+            conversation_buttons.push(conversation::conversation_button(msg, i + 1));
+            if edited.contains(&msg.message_id) {
+                history_buttons.push(history::history_button(msg, i + 1));
+            }
         }
-        ctx.author().direct_message(ctx, msg_builder).await?;
+        // action row 하나에 버튼 최대 5개, 결과 10개면 대화 2줄 + 수정 기록 최대 2줄
+        let rows = conversation_buttons
+            .chunks(5)
+            .chain(history_buttons.chunks(5))
+            .map(|row| CreateActionRow::Buttons(row.to_vec()))
+            .collect();
+        ctx.author()
+            .direct_message(ctx, msg_builder.components(rows))
+            .await?;
+    }
+    Ok(())
+}
+
+// 캐시에는 없지만 api로 가져온 결과에 섞일 수 있음
+pub(super) async fn hide_opted_out(
+    storage: &dyn Storage,
+    mut results: Vec<SearchResult>,
+) -> Result<Vec<SearchResult>, Error> {
+    let authors = results.iter().map(|r| r.author_id).collect::<Vec<_>>();
+    let opted_out = storage.list_opted_out_users(&authors).await?;
+    for result in results
+        .iter_mut()
+        .filter(|r| opted_out.contains(&r.author_id))
+    {
+        result.content = OPTED_OUT_CONTENT.to_owned();
+    }
+    Ok(results)
+}
+
+// 검색 결과 dm에 붙은 버튼 처리. 세션이 끝난 뒤에도 눌릴 수 있으니 collector 대신 이벤트로 받음
+pub(super) async fn handle_component(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &ComponentInteraction,
+) -> Result<(), Error> {
+    let custom_id = &interaction.data.custom_id;
+    if let Some(target) = conversation::parse_conversation_id(custom_id) {
+        conversation::show_conversation(ctx, data, interaction, target).await?;
+    } else if let Some(target) = history::parse_history_id(custom_id) {
+        history::show_history(ctx, data, interaction, target).await?;
     }
     Ok(())
 }
@@ -0,0 +1,140 @@
+use super::logic::{parse_custom_id, result_embed};
+use crate::{Data, Error, database::SearchResult, storage::Storage};
+use poise::serenity_prelude::{
+    self as serenity, ChannelId, ComponentInteraction, CreateButton, CreateInteractionResponse,
+    CreateMessage, MessageId,
+};
+use std::collections::{BTreeMap, VecDeque, btree_map::Entry};
+
+const CONVERSATION_PREFIX: &str = "conversation";
+const MAX_CONVERSATION_MESSAGES: usize = 30; // 답장이 너무 많으면 api 호출, dm 폭탄이 됨
+
+pub(super) fn conversation_button(result: &SearchResult, index: usize) -> CreateButton {
+    CreateButton::new(format!(
+        "{CONVERSATION_PREFIX}:{}:{}:{}",
+        result.guild_id, result.channel_id, result.message_id
+    ))
+    .label(format!("💬 {index}"))
+    .style(serenity::ButtonStyle::Secondary)
+}
+
+// (guild_id, channel_id, message_id)
+pub(super) fn parse_conversation_id(custom_id: &str) -> Option<(i64, i64, i64)> {
+    let [guild_id, channel_id, message_id] = parse_custom_id(custom_id, CONVERSATION_PREFIX)?;
+    Some((guild_id, channel_id, message_id))
+}
+
+pub(super) async fn show_conversation(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &ComponentInteraction,
+    (guild_id, channel_id, message_id): (i64, i64, i64),
+) -> Result<(), Error> {
+    // api 호출로 3초 넘길 수 있으니 먼저 응답
+    interaction
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let conversation =
+        collect_conversation(ctx, data.storage.as_ref(), guild_id, channel_id, message_id).await?;
+    let conversation = super::hide_opted_out(data.storage.as_ref(), conversation).await?;
+
+    if conversation.is_empty() {
+        interaction
+            .channel_id
+            .send_message(
+                ctx,
+                CreateMessage::new()
+                    .content("원본 메세지를 찾을 수 없습니다. 삭제되었거나 권한이 없습니다.")
+                    .reference_message(&*interaction.message),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    for chunk in conversation.chunks(10) {
+        let mut msg_builder = CreateMessage::new().reference_message(&*interaction.message);
+        for msg in chunk {
+            let prefix = if msg.message_id == message_id {
+                "▶ "
+            } else {
+                ""
+            };
+            msg_builder = msg_builder.add_embed(result_embed(msg, prefix, 100));
+        }
+        interaction
+            .channel_id
+            .send_message(ctx, msg_builder)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// 검색 결과에서 답장 대상을 따라 위로, 그 메세지에 달린 답장을 따라 아래로 탐색
+async fn collect_conversation(
+    ctx: &serenity::Context,
+    storage: &dyn Storage,
+    guild_id: i64,
+    channel_id: i64,
+    message_id: i64,
+) -> Result<Vec<SearchResult>, Error> {
+    let mut conversation = BTreeMap::new();
+
+    let Some(hit) = load_message(ctx, storage, guild_id, channel_id, message_id).await? else {
+        return Ok(vec![]);
+    };
+
+    let mut parent = hit.reply_to_id;
+    conversation.insert(hit.message_id, hit);
+
+    while let Some(parent_id) = parent
+        && conversation.len() < MAX_CONVERSATION_MESSAGES
+        && !conversation.contains_key(&parent_id)
+    {
+        let Some(msg) = load_message(ctx, storage, guild_id, channel_id, parent_id).await? else {
+            break;
+        };
+        parent = msg.reply_to_id;
+        conversation.insert(parent_id, msg);
+    }
+
+    // 답장 목록은 api로 조회할 방법이 없으니 캐시에 있는 것만 찾음
+    let mut queue = VecDeque::from([message_id]);
+    while let Some(id) = queue.pop_front() {
+        let remaining = MAX_CONVERSATION_MESSAGES.saturating_sub(conversation.len());
+        if remaining == 0 {
+            break;
+        }
+        for reply in storage.get_replies(id, remaining as u32).await? {
+            if let Entry::Vacant(entry) = conversation.entry(reply.message_id) {
+                queue.push_back(reply.message_id);
+                entry.insert(reply);
+            }
+        }
+    }
+
+    // message id 순서 == 작성 순서
+    Ok(conversation.into_values().collect())
+}
+
+async fn load_message(
+    ctx: &serenity::Context,
+    storage: &dyn Storage,
+    guild_id: i64,
+    channel_id: i64,
+    message_id: i64,
+) -> Result<Option<SearchResult>, Error> {
+    if let Some(msg) = storage.get_message(message_id).await? {
+        return Ok(Some(msg));
+    }
+
+    match ChannelId::new(channel_id as u64)
+        .message(ctx, MessageId::new(message_id as u64))
+        .await
+    {
+        Ok(msg) => Ok(Some(SearchResult::from_message(&msg, guild_id))),
+        // 삭제된 메세지거나 채널 접근 권한 없음
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,101 @@
+use super::logic::{parse_custom_id, substr, timestamp_to_readable};
+use crate::{Data, Error, database::SearchResult};
+use poise::serenity_prelude::{
+    self as serenity, ComponentInteraction, CreateButton, CreateEmbed, CreateInteractionResponse,
+    CreateMessage, GuildId,
+};
+
+const HISTORY_PREFIX: &str = "history";
+const MAX_HISTORY_FIELDS: usize = 24; // embed field 최대 25개, 하나는 현재 내용
+
+pub(super) fn history_button(result: &SearchResult, index: usize) -> CreateButton {
+    CreateButton::new(format!(
+        "{HISTORY_PREFIX}:{}:{}",
+        result.guild_id, result.message_id
+    ))
+    .label(format!("🕘 {index}"))
+    .style(serenity::ButtonStyle::Secondary)
+}
+
+// (guild_id, message_id)
+pub(super) fn parse_history_id(custom_id: &str) -> Option<(i64, i64)> {
+    let [guild_id, message_id] = parse_custom_id(custom_id, HISTORY_PREFIX)?;
+    Some((guild_id, message_id))
+}
+
+pub(super) async fn show_history(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &ComponentInteraction,
+    (guild_id, message_id): (i64, i64),
+) -> Result<(), Error> {
+    interaction
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let storage = &data.storage;
+    let reply = |content: &str| {
+        CreateMessage::new()
+            .content(content)
+            .reference_message(&*interaction.message)
+    };
+
+    // 버튼을 보낸 뒤 서버에서 수정 기록 보관을 껐을 수 있음
+    if !data
+        .settings
+        .is_revisions_enabled(GuildId::new(guild_id as u64))
+        .await?
+    {
+        interaction
+            .channel_id
+            .send_message(
+                ctx,
+                reply("이 서버는 메세지 수정 기록 보관이 꺼져 있습니다."),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let Some(current) = storage.get_message(message_id).await? else {
+        interaction
+            .channel_id
+            .send_message(ctx, reply("캐시에서 메세지를 찾을 수 없습니다."))
+            .await?;
+        return Ok(());
+    };
+    let revisions = storage.list_message_revisions(message_id).await?;
+
+    let mut embed = CreateEmbed::new().title(current.link());
+    let skip = revisions.len().saturating_sub(MAX_HISTORY_FIELDS);
+    for (i, revision) in revisions.iter().enumerate().skip(skip) {
+        let edited_at =
+            serenity::Timestamp::from_unix_timestamp(revision.edited_at).unwrap_or_default();
+        embed = embed.field(
+            format!("{}. ~ {}", i + 1, timestamp_to_readable(edited_at)),
+            field_content(&revision.content),
+            false,
+        );
+    }
+    embed = embed.field("현재", field_content(&current.content), false);
+
+    interaction
+        .channel_id
+        .send_message(
+            ctx,
+            CreateMessage::new()
+                .add_embed(embed)
+                .reference_message(&*interaction.message),
+        )
+        .await?;
+
+    Ok(())
+}
+
+// 빈 field는 discord가 거부함
+fn field_content(content: &str) -> String {
+    if content.is_empty() {
+        "(내용 없음)".to_owned()
+    } else {
+        substr(content, 200).to_owned()
+    }
+}
@@ -1,5 +1,9 @@
+use crate::database::SearchResult;
 use chrono::DateTime;
-use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::{self as serenity, CreateEmbed, CreateEmbedAuthor};
+
+// /privacy forget-me를 실행한 사용자의 메세지
+pub const OPTED_OUT_CONTENT: &str = "*(캐싱을 거부한 사용자의 메세지입니다)*";
 
 pub fn timestamp_to_readable(timestamp: serenity::Timestamp) -> String {
     let datetime = DateTime::from_timestamp(timestamp.unix_timestamp(), 0).unwrap_or_default();
@@ -12,3 +16,45 @@ pub fn substr(content: &str, n: usize) -> &str {
         None => content,
     }
 }
+
+// embed field 하나로 표시할 (title, content)
+pub fn result_field(msg: &SearchResult, prefix: &str, max_chars: usize) -> (String, String) {
+    let timestamp = serenity::Timestamp::from_unix_timestamp(msg.created_at).unwrap_or_default();
+    let title = format!(
+        "{}{}\t{}",
+        prefix,
+        &timestamp_to_readable(timestamp),
+        msg.link(),
+    );
+    let first_3_lines = msg.content.lines().take(3).collect::<Vec<_>>().join("\n");
+    let content = substr(&first_3_lines, max_chars).to_string();
+    (title, content)
+}
+
+// 결과 하나를 embed 하나로. 작성자는 표시 이름과 프로필 사진으로 보여줌
+pub fn result_embed(msg: &SearchResult, prefix: &str, max_chars: usize) -> CreateEmbed {
+    let (title, content) = result_field(msg, prefix, max_chars);
+    let mut author = CreateEmbedAuthor::new(msg.author_display_name());
+    if let Some(avatar_url) = &msg.avatar_url {
+        author = author.icon_url(avatar_url);
+    }
+    CreateEmbed::new()
+        .author(author)
+        .field(title, content, false)
+}
+
+// "prefix:1:2:3" 형태의 버튼 custom id에서 id들을 꺼냄
+pub fn parse_custom_id<const N: usize>(custom_id: &str, prefix: &str) -> Option<[i64; N]> {
+    let mut parts = custom_id.split(':');
+    if parts.next()? != prefix {
+        return None;
+    }
+    let mut ids = [0; N];
+    for id in ids.iter_mut() {
+        *id = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ids)
+}
@@ -1,3 +1,5 @@
+use crate::command::search::conversation::parse_conversation_id;
+use crate::command::search::history::parse_history_id;
 use crate::command::search::logic::substr;
 
 #[test]
@@ -8,3 +10,18 @@ fn test_substr() {
     assert_eq!(substr("안녕하세요", 7), "안녕하세요");
     assert_eq!(substr("안녕하세요", 2), "안녕");
 }
+
+#[test]
+fn test_parse_conversation_id() {
+    assert_eq!(parse_conversation_id("conversation:1:2:3"), Some((1, 2, 3)));
+    assert_eq!(parse_conversation_id("search more"), None);
+    assert_eq!(parse_conversation_id("conversation:1:2"), None);
+    assert_eq!(parse_conversation_id("conversation:1:2:3:4"), None);
+}
+
+#[test]
+fn test_parse_history_id() {
+    assert_eq!(parse_history_id("history:1:2"), Some((1, 2)));
+    assert_eq!(parse_history_id("conversation:1:2:3"), None);
+    assert_eq!(parse_history_id("history:1:x"), None);
+}
@@ -0,0 +1,547 @@
+use crate::shard::Sharding;
+use serde::Deserialize;
+use sqlx::sqlite::SqliteConnectOptions;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+const DEFAULT_FILE: &str = "config.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// 봇 설정. config.toml(CONFIG_FILE로 경로 변경)을 읽고 환경 변수로 덮어씀
+// 파일에도 환경 변수에도 없는 항목은 기본값
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub discord: DiscordConfig,
+    pub database: DatabaseConfig,
+    pub search: SearchConfig,
+    pub ingest: IngestConfig,
+    pub tasks: TaskConfig,
+    pub retention: RetentionConfig,
+    pub backup: BackupConfig,
+    pub shard: ShardConfig,
+    pub log: LogConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscordConfig {
+    pub token: Option<String>,       // DISCORD_TOKEN
+    pub token_debug: Option<String>, // DISCORD_TOKEN_DEBUG
+    // token_debug를 쓰고, 명령어를 서버마다 등록. 기본값은 디버그 빌드 여부
+    pub dev_mode: bool, // DEV_MODE
+    // 별명 변경을 받으려면 developer portal에서 Server Members Intent를 켜야 함
+    pub members_intent: bool, // GUILD_MEMBERS_INTENT
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            token_debug: None,
+            dev_mode: cfg!(debug_assertions),
+            members_intent: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String, // DATABASE_URL
+    // 앞에서부터 찾아서 처음 있는 파일을 SQLCipher 키로 씀
+    pub encryption_key_paths: Vec<PathBuf>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite://discord_bot.db?mode=rwc".to_owned(),
+            encryption_key_paths: vec![
+                "/run/secrets/db_key".into(), // Docker secret mount
+                "secrets/db_key".into(),      // Native local run
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    // 캐시에 없으면 discord api로 100개씩 몇 번 가져올지
+    pub count: usize,
+    // 메세지 하나에 보여줄 결과 수
+    pub page_size: u32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            count: 10,
+            page_size: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestConfig {
+    pub flush_ms: u64,     // INGEST_FLUSH_MS
+    pub queue_size: usize, // INGEST_QUEUE_SIZE
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            flush_ms: 100,
+            queue_size: 10_000,
+        }
+    }
+}
+
+// 주기 작업. 0이면 끔
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaskConfig {
+    pub backfill_interval_ms: u64,        // BACKFILL_INTERVAL_MS
+    pub retention_interval_secs: u64,     // RETENTION_INTERVAL_SECS
+    pub maintenance_interval_secs: u64,   // MAINTENANCE_INTERVAL_SECS
+    pub version_check_interval_secs: u64, // VERSION_CHECK_INTERVAL_SECS
+}
+
+impl Default for TaskConfig {
+    fn default() -> Self {
+        Self {
+            backfill_interval_ms: 1_000,
+            retention_interval_secs: 3_600,
+            maintenance_interval_secs: 604_800,
+            version_check_interval_secs: 86_400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    // 서버, 채널 설정과 상관없이 이보다 오래된 메세지는 보관하지 않음
+    pub max_age_days: i64, // RETENTION_MAX_AGE_DAYS
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self { max_age_days: 180 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    // 없으면 주기 백업을 하지 않음
+    pub dir: Option<PathBuf>, // BACKUP_DIR
+    pub interval_secs: u64,   // BACKUP_INTERVAL_SECS
+    pub keep: usize,          // BACKUP_KEEP
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            interval_secs: 86_400,
+            keep: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShardConfig {
+    pub count: Option<ShardCount>, // SHARD_COUNT, 숫자나 auto
+    pub range: Option<String>,     // SHARD_RANGE, first-last
+    pub status_interval_secs: u64, // SHARD_STATUS_INTERVAL_SECS
+    // validate에서 count, range로 채움
+    #[serde(skip)]
+    pub sharding: Sharding,
+}
+
+// 파일에서 count = 4와 count = "auto"를 모두 받음. 값 검사는 Sharding::parse에서
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ShardCount {
+    Number(i64),
+    Text(String),
+}
+
+impl Display for ShardCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShardCount::Number(n) => write!(f, "{}", n),
+            ShardCount::Text(v) => f.write_str(v),
+        }
+    }
+}
+
+impl Default for ShardConfig {
+    fn default() -> Self {
+        Self {
+            count: None,
+            range: None,
+            status_interval_secs: 300,
+            sharding: Sharding::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub filter: String, // RUST_LOG
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "discord_search_bot=info,warn".to_owned(),
+        }
+    }
+}
+
+impl Config {
+    // CONFIG_FILE을 줬는데 파일이 없으면 오류. 기본 경로의 파일은 없어도 됨
+    pub fn load() -> Result<Self, String> {
+        let mut config = match std::env::var("CONFIG_FILE") {
+            Ok(path) if !path.is_empty() => Self::from_file(Path::new(&path))?,
+            _ if Path::new(DEFAULT_FILE).exists() => Self::from_file(Path::new(DEFAULT_FILE))?,
+            _ => Self::default(),
+        };
+        let mut errors = Vec::new();
+        config.apply_env(|key| std::env::var(key).ok(), &mut errors);
+        config.validate(&mut errors);
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("invalid config file {}: {}", path.display(), e))
+    }
+
+    // 빈 값은 설정하지 않은 것으로 봄
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>, errors: &mut Vec<String>) {
+        let var = |key: &str| var(key).filter(|v| !v.is_empty());
+
+        if let Some(v) = var("DISCORD_TOKEN") {
+            self.discord.token = Some(v);
+        }
+        if let Some(v) = var("DISCORD_TOKEN_DEBUG") {
+            self.discord.token_debug = Some(v);
+        }
+        override_bool(&var, "DEV_MODE", &mut self.discord.dev_mode, errors);
+        override_bool(
+            &var,
+            "GUILD_MEMBERS_INTENT",
+            &mut self.discord.members_intent,
+            errors,
+        );
+        if let Some(v) = var("DATABASE_URL") {
+            self.database.url = v;
+        }
+        override_value(&var, "INGEST_FLUSH_MS", &mut self.ingest.flush_ms, errors);
+        override_value(
+            &var,
+            "INGEST_QUEUE_SIZE",
+            &mut self.ingest.queue_size,
+            errors,
+        );
+        let tasks = &mut self.tasks;
+        override_value(
+            &var,
+            "BACKFILL_INTERVAL_MS",
+            &mut tasks.backfill_interval_ms,
+            errors,
+        );
+        override_value(
+            &var,
+            "RETENTION_INTERVAL_SECS",
+            &mut tasks.retention_interval_secs,
+            errors,
+        );
+        override_value(
+            &var,
+            "MAINTENANCE_INTERVAL_SECS",
+            &mut tasks.maintenance_interval_secs,
+            errors,
+        );
+        override_value(
+            &var,
+            "VERSION_CHECK_INTERVAL_SECS",
+            &mut tasks.version_check_interval_secs,
+            errors,
+        );
+        override_value(
+            &var,
+            "RETENTION_MAX_AGE_DAYS",
+            &mut self.retention.max_age_days,
+            errors,
+        );
+        if let Some(v) = var("BACKUP_DIR") {
+            self.backup.dir = Some(v.into());
+        }
+        override_value(
+            &var,
+            "BACKUP_INTERVAL_SECS",
+            &mut self.backup.interval_secs,
+            errors,
+        );
+        override_value(&var, "BACKUP_KEEP", &mut self.backup.keep, errors);
+        if let Some(v) = var("SHARD_COUNT") {
+            self.shard.count = Some(ShardCount::Text(v));
+        }
+        if let Some(v) = var("SHARD_RANGE") {
+            self.shard.range = Some(v);
+        }
+        override_value(
+            &var,
+            "SHARD_STATUS_INTERVAL_SECS",
+            &mut self.shard.status_interval_secs,
+            errors,
+        );
+        if let Some(v) = var("RUST_LOG") {
+            self.log.filter = v;
+        }
+    }
+
+    fn validate(&mut self, errors: &mut Vec<String>) {
+        if !crate::storage::is_postgres_url(&self.database.url)
+            && let Err(e) = SqliteConnectOptions::from_str(&self.database.url)
+        {
+            errors.push(format!("database.url is not a valid database url: {}", e));
+        }
+        if self.search.count == 0 {
+            errors.push("search.count must be at least 1".to_owned());
+        }
+        if self.search.page_size == 0 {
+            errors.push("search.page_size must be at least 1".to_owned());
+        }
+        if self.ingest.queue_size == 0 {
+            errors.push("ingest.queue_size must be at least 1".to_owned());
+        }
+        if self.retention.max_age_days < 1 {
+            errors.push("retention.max_age_days must be at least 1".to_owned());
+        }
+        if self.backup.keep == 0 {
+            errors.push("backup.keep must be at least 1".to_owned());
+        }
+        let count = self.shard.count.as_ref().map(ShardCount::to_string);
+        match Sharding::parse(count.as_deref(), self.shard.range.as_deref()) {
+            Ok(sharding) => self.shard.sharding = sharding,
+            Err(e) => errors.push(e),
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            errors.push(format!("log.filter is not a valid filter: {}", e));
+        }
+    }
+
+    // 실행 인자로 토큰을 주지 않았을 때 쓰는 토큰
+    pub fn token(&self) -> Option<&str> {
+        if self.discord.dev_mode {
+            self.discord.token_debug.as_deref()
+        } else {
+            self.discord.token.as_deref()
+        }
+    }
+}
+
+fn override_value<T>(
+    var: &impl Fn(&str) -> Option<String>,
+    key: &str,
+    target: &mut T,
+    errors: &mut Vec<String>,
+) where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(v) = var(key) {
+        match v.trim().parse() {
+            Ok(value) => *target = value,
+            Err(e) => errors.push(format!("{} is not valid ({}): {}", key, e, v)),
+        }
+    }
+}
+
+fn override_bool(
+    var: &impl Fn(&str) -> Option<String>,
+    key: &str,
+    target: &mut bool,
+    errors: &mut Vec<String>,
+) {
+    if let Some(v) = var(key) {
+        match v.trim() {
+            "1" | "true" => *target = true,
+            "0" | "false" => *target = false,
+            _ => errors.push(format!("{} must be 1, 0, true or false: {}", key, v)),
+        }
+    }
+}
+
+// main에서 한 번 설정. 그 전에 부르면(테스트, 벤치) 기본값
+pub fn init(config: Config) -> &'static Config {
+    CONFIG.get_or_init(|| config)
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn with_env(config: &mut Config, vars: &[(&str, &str)]) -> Vec<String> {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        let mut errors = Vec::new();
+        config.apply_env(|key| vars.get(key).map(|v| v.to_string()), &mut errors);
+        config.validate(&mut errors);
+        errors
+    }
+
+    #[test]
+    fn defaults_match_previous_behavior() {
+        let mut config = Config::default();
+        assert!(with_env(&mut config, &[]).is_empty());
+
+        assert_eq!(config.database.url, "sqlite://discord_bot.db?mode=rwc");
+        assert_eq!(config.search.count, 10);
+        assert_eq!(config.search.page_size, 10);
+        assert_eq!(config.tasks.version_check_interval_secs, 86_400);
+        assert_eq!(config.retention.max_age_days, 180);
+        assert_eq!(config.backup.keep, 7);
+        assert_eq!(config.shard.sharding, Sharding::Count(1));
+        assert_eq!(config.log.filter, "discord_search_bot=info,warn");
+        assert_eq!(config.discord.dev_mode, cfg!(debug_assertions));
+    }
+
+    #[test]
+    fn file_values_are_overridden_by_env() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [discord]
+            token = "file-token"
+            dev_mode = false
+
+            [database]
+            url = "sqlite://file.db"
+            encryption_key_paths = ["keys/db_key"]
+
+            [search]
+            count = 3
+
+            [shard]
+            count = 4
+            "#,
+        )
+        .unwrap();
+        let errors = with_env(
+            &mut config,
+            &[
+                ("DATABASE_URL", "postgres://localhost/bot"),
+                ("VERSION_CHECK_INTERVAL_SECS", "0"),
+                ("SHARD_RANGE", "2-3"),
+                ("DISCORD_TOKEN_DEBUG", ""),
+            ],
+        );
+        assert!(errors.is_empty());
+
+        assert_eq!(config.token(), Some("file-token"));
+        assert_eq!(config.database.url, "postgres://localhost/bot");
+        assert_eq!(
+            config.database.encryption_key_paths,
+            [PathBuf::from("keys/db_key")]
+        );
+        assert_eq!(config.search.count, 3);
+        assert_eq!(config.search.page_size, 10);
+        assert_eq!(config.tasks.version_check_interval_secs, 0);
+        assert_eq!(config.discord.token_debug, None);
+        assert_eq!(
+            config.shard.sharding,
+            Sharding::Range {
+                first: 2,
+                last: 3,
+                total: 4
+            }
+        );
+    }
+
+    #[test]
+    fn dev_mode_uses_debug_token() {
+        let mut config = Config::default();
+        let errors = with_env(
+            &mut config,
+            &[
+                ("DISCORD_TOKEN", "release"),
+                ("DISCORD_TOKEN_DEBUG", "debug"),
+                ("DEV_MODE", "1"),
+            ],
+        );
+        assert!(errors.is_empty());
+        assert_eq!(config.token(), Some("debug"));
+    }
+
+    #[test]
+    fn reports_every_invalid_value() {
+        let mut config: Config = toml::from_str("[search]\npage_size = 0").unwrap();
+        let errors = with_env(
+            &mut config,
+            &[
+                ("INGEST_FLUSH_MS", "soon"),
+                ("GUILD_MEMBERS_INTENT", "yes"),
+                ("SHARD_COUNT", "0"),
+                ("RUST_LOG", "info,=["),
+            ],
+        );
+
+        assert_eq!(errors.len(), 5, "{errors:?}");
+        for key in [
+            "INGEST_FLUSH_MS",
+            "GUILD_MEMBERS_INTENT",
+            "SHARD_COUNT",
+            "search.page_size",
+            "log.filter",
+        ] {
+            assert!(errors.iter().any(|e| e.contains(key)), "{key}: {errors:?}");
+        }
+    }
+
+    #[test]
+    fn shard_count_accepts_number_or_text() {
+        for (file, expected) in [
+            ("count = 4", Sharding::Count(4)),
+            ("count = \"4\"", Sharding::Count(4)),
+            ("count = \"auto\"", Sharding::Auto),
+        ] {
+            let mut config: Config = toml::from_str(&format!("[shard]\n{}", file)).unwrap();
+            assert!(with_env(&mut config, &[]).is_empty(), "{file}");
+            assert_eq!(config.shard.sharding, expected, "{file}");
+        }
+
+        let mut config: Config = toml::from_str("[shard]\ncount = -1").unwrap();
+        let errors = with_env(&mut config, &[]);
+        assert!(
+            errors.iter().any(|e| e.contains("SHARD_COUNT")),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("[search]\ncuont = 3").is_err());
+        assert!(toml::from_str::<Config>("[serach]\ncount = 3").is_err());
+    }
+}
@@ -1,24 +1,26 @@
+use crate::redaction::{Detector, RedactionRules, Redactor};
 use poise::serenity_prelude as serenity;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
-use sqlx::{Row, SqlitePool};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{ConnectOptions, Connection, Row, SqliteConnection, SqlitePool};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-const DB_ENCRYPTION_KEY_PATHS: &[&str] = &[
-    "/run/secrets/db_key", // Docker secret mount
-    "secrets/db_key",      // Native local run
-];
-
-#[derive(sqlx::FromRow, Debug)]
+#[derive(sqlx::FromRow, Debug, Clone)]
 pub struct SearchResult {
     pub message_id: i64,
     pub channel_id: i64,
     pub guild_id: i64,
-    #[allow(unused)]
     pub author_id: i64,
     pub author_name: String,
     pub content: String,
     pub created_at: i64,
+    pub reply_to_id: Option<i64>,
+    // 서버 별명 > 전역 표시 이름. members에 없으면 None
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
 }
 
 impl SearchResult {
@@ -39,15 +41,38 @@ impl SearchResult {
             author_name: msg.author.name.clone(),
             content: msg.content.clone(),
             created_at: msg.timestamp.timestamp(),
+            reply_to_id: reply_to_id(msg),
+            display_name: msg
+                .member
+                .as_ref()
+                .and_then(|m| m.nick.clone())
+                .or_else(|| msg.author.global_name.clone()),
+            avatar_url: Some(msg.author.face()),
         }
     }
+
+    pub fn author_display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.author_name)
+    }
 }
 
-pub async fn init_db() -> Result<SqlitePool, sqlx::Error> {
-    let database_url = std::env::var("DATABASE_URL")
-        .unwrap_or_else(|_| "sqlite://discord_bot.db?mode=rwc".to_string());
+// 같은 채널 안의 답장만 대화로 취급. forward, crosspost 등은 무시
+pub fn reply_to_id(msg: &serenity::Message) -> Option<i64> {
+    let reference = msg.message_reference.as_ref()?;
+    if reference.kind != serenity::MessageReferenceKind::Default
+        || reference.channel_id != msg.channel_id
+    {
+        return None;
+    }
+    reference.message_id.map(|id| id.get() as i64)
+}
+
+pub fn database_url() -> String {
+    crate::config::get().database.url.clone()
+}
 
-    let mut options = SqliteConnectOptions::from_str(&database_url)?.create_if_missing(true);
+pub async fn init_db() -> Result<SqlitePool, sqlx::Error> {
+    let mut options = SqliteConnectOptions::from_str(&database_url())?.create_if_missing(true);
 
     if let Some(key) = database_encryption_key()? {
         let encrypted_filename = encrypted_database_filename(options.get_filename());
@@ -66,8 +91,8 @@ pub async fn init_db() -> Result<SqlitePool, sqlx::Error> {
     Ok(pool)
 }
 
-fn database_encryption_key() -> Result<Option<String>, sqlx::Error> {
-    for path in DB_ENCRYPTION_KEY_PATHS.iter().map(Path::new) {
+pub fn database_encryption_key() -> Result<Option<String>, sqlx::Error> {
+    for path in &crate::config::get().database.encryption_key_paths {
         if path.exists() {
             let key = std::fs::read_to_string(path)?;
             return validate_encryption_key(key).map(Some);
@@ -77,7 +102,13 @@ fn database_encryption_key() -> Result<Option<String>, sqlx::Error> {
     Ok(None)
 }
 
-fn encrypted_database_filename(filename: &Path) -> PathBuf {
+// DATABASE_URL이 가리키는 평문 DB 파일. 키 파일이 있으면 봇은 encrypted_database_filename을 엶
+pub fn database_filename() -> Result<PathBuf, sqlx::Error> {
+    let options = SqliteConnectOptions::from_str(&database_url())?;
+    Ok(options.get_filename().to_path_buf())
+}
+
+pub fn encrypted_database_filename(filename: &Path) -> PathBuf {
     if filename == Path::new(":memory:") {
         return filename.to_path_buf();
     }
@@ -104,6 +135,125 @@ fn validate_encryption_key(key: String) -> Result<String, sqlx::Error> {
     Ok(key)
 }
 
+// 평문 DB를 새 파일에 암호화해서 복사. 원본은 그대로 둠
+pub async fn encrypt_database(
+    plaintext: &Path,
+    encrypted: &Path,
+    key: &str,
+) -> Result<(), sqlx::Error> {
+    export_database(plaintext, None, encrypted, key).await
+}
+
+// 디버깅용으로 암호화된 DB를 평문 파일로 복사
+pub async fn decrypt_database(
+    encrypted: &Path,
+    key: &str,
+    plaintext: &Path,
+) -> Result<(), sqlx::Error> {
+    export_database(encrypted, Some(key), plaintext, "").await
+}
+
+// 키 파일은 바꾸지 않음. 성공하면 새 키로 키 파일을 교체해야 함
+pub async fn rekey_database(path: &Path, key: &str, new_key: &str) -> Result<(), sqlx::Error> {
+    let new_key = validate_encryption_key(new_key.to_owned())?;
+    let mut conn = connect_file(path, Some(key)).await?;
+    sqlx::query(&format!("PRAGMA rekey = {}", sql_string_literal(&new_key)))
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+
+    // 새 키로 열리는지 확인
+    connect_file(path, Some(&new_key)).await?.close().await
+}
+
+// sqlcipher_export로 스키마와 데이터를 통째로 복사. 빈 키면 평문으로 저장됨
+async fn export_database(
+    source: &Path,
+    source_key: Option<&str>,
+    target: &Path,
+    target_key: &str,
+) -> Result<(), sqlx::Error> {
+    if target.exists() {
+        return Err(sqlx::Error::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        )));
+    }
+
+    let mut conn = connect_file(source, source_key).await?;
+    sqlx::query("ATTACH DATABASE ? AS target KEY ?")
+        .bind(target.to_string_lossy())
+        .bind(target_key)
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("SELECT sqlcipher_export('target')")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("DETACH DATABASE target")
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+
+    let target_key = (!target_key.is_empty()).then_some(target_key);
+    connect_file(target, target_key).await?.close().await
+}
+
+// 파일 하나를 다루는 도구용 연결. WAL 파일이 남지 않도록 rollback journal을 씀
+// ATTACH로 새 파일을 만들 수 있게 create 플래그를 켜므로 없는 파일은 미리 거름
+async fn connect_file(path: &Path, key: Option<&str>) -> Result<SqliteConnection, sqlx::Error> {
+    if !path.exists() {
+        return Err(sqlx::Error::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} does not exist", path.display()),
+        )));
+    }
+    let mut options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Delete);
+    if let Some(key) = key {
+        options = options.pragma("key", sql_string_literal(key));
+    }
+    let mut conn = options.connect().await?;
+    // 키가 틀리면 첫 읽기에서 실패
+    sqlx::query("SELECT count(*) FROM sqlite_master")
+        .execute(&mut conn)
+        .await?;
+    Ok(conn)
+}
+
+// 쓰는 중에도 한 시점의 일관된 복사본을 만듦. 암호화된 DB는 같은 키로 암호화된 채로 저장됨
+pub async fn backup_database(pool: &SqlitePool, target: &Path) -> Result<(), sqlx::Error> {
+    if target.exists() {
+        return Err(sqlx::Error::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        )));
+    }
+    sqlx::query("VACUUM INTO ?")
+        .bind(target.to_string_lossy())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// 백업이 키로 열리고 손상되지 않았는지 확인
+pub async fn verify_database(path: &Path, key: Option<&str>) -> Result<(), sqlx::Error> {
+    let mut conn = connect_file(path, key).await?;
+    let result: String = sqlx::query_scalar("PRAGMA quick_check")
+        .fetch_one(&mut conn)
+        .await?;
+    conn.close().await?;
+    if result != "ok" {
+        return Err(sqlx::Error::Protocol(format!(
+            "{} failed quick_check: {}",
+            path.display(),
+            result
+        )));
+    }
+    Ok(())
+}
+
 fn sql_string_literal(value: &str) -> String {
     format!("'{}'", value.replace('\'', "''"))
 }
@@ -117,8 +267,7 @@ fn config_error(message: &'static str) -> sqlx::Error {
 
 #[cfg(test)]
 mod encryption_tests {
-    use super::encrypted_database_filename;
-    use std::path::Path;
+    use super::*;
 
     #[test]
     fn encrypted_database_filename_keeps_plaintext_db_separate() {
@@ -135,6 +284,51 @@ mod encryption_tests {
             Path::new(":memory:")
         );
     }
+
+    async fn read_value(path: &Path, key: Option<&str>) -> Result<String, sqlx::Error> {
+        let mut conn = connect_file(path, key).await?;
+        let value = sqlx::query_scalar("SELECT value FROM t")
+            .fetch_one(&mut conn)
+            .await?;
+        conn.close().await?;
+        Ok(value)
+    }
+
+    #[tokio::test]
+    async fn encrypt_rekey_and_decrypt_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let plaintext = dir.path().join("discord_bot.db");
+        let encrypted = encrypted_database_filename(&plaintext);
+        let decrypted = dir.path().join("decrypted.db");
+
+        let mut conn = SqliteConnectOptions::new()
+            .filename(&plaintext)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE t (value TEXT); INSERT INTO t VALUES ('안녕')")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        conn.close().await.unwrap();
+
+        encrypt_database(&plaintext, &encrypted, "old key").await.unwrap();
+        assert_eq!(read_value(&encrypted, Some("old key")).await.unwrap(), "안녕");
+        assert!(read_value(&encrypted, None).await.is_err());
+        // 이미 있는 파일은 덮어쓰지 않음
+        assert!(encrypt_database(&plaintext, &encrypted, "old key").await.is_err());
+
+        rekey_database(&encrypted, "old key", "new key").await.unwrap();
+        assert!(read_value(&encrypted, Some("old key")).await.is_err());
+        assert_eq!(read_value(&encrypted, Some("new key")).await.unwrap(), "안녕");
+        assert!(rekey_database(&encrypted, "old key", "other").await.is_err());
+
+        assert!(decrypt_database(&encrypted, "old key", &decrypted).await.is_err());
+        assert!(!decrypted.exists());
+        decrypt_database(&encrypted, "new key", &decrypted).await.unwrap();
+        assert_eq!(read_value(&decrypted, None).await.unwrap(), "안녕");
+    }
 }
 
 use std::cmp::{max, min};
@@ -161,6 +355,11 @@ impl Range {
         self.start <= other.start && self.end >= other.end
     }
 
+    // min_start보다 오래된 부분을 잘라냄. 전부 오래됐으면 None
+    pub fn clamp_start(&self, min_start: i64) -> Option<Range> {
+        (self.end >= min_start).then(|| Range::new(max(self.start, min_start), self.end))
+    }
+
     // 두 range가 연속된 하나의 range로 합칠 수 있는지
     pub fn merge(&self, other: &Range) -> Option<Range> {
         if self.touches(other) {
@@ -194,6 +393,15 @@ pub fn merge_ranges(mut ranges: Vec<Range>, new_range: Range) -> Vec<Range> {
     merged
 }
 
+// 정렬, 병합된 range 사이에서 캐시되지 않은 id 구간
+pub fn sync_gaps(ranges: &[Range]) -> Vec<Range> {
+    ranges
+        .windows(2)
+        .filter(|w| w[0].end + 1 < w[1].start)
+        .map(|w| Range::new(w[0].end + 1, w[1].start - 1))
+        .collect()
+}
+
 pub async fn add_sync_range(
     pool: &SqlitePool,
     channel_id: i64,
@@ -202,81 +410,335 @@ pub async fn add_sync_range(
 ) -> Result<Range, sqlx::Error> {
     // transaction
     let mut tx = pool.begin().await?;
+    let merged = add_sync_range_in(&mut tx, channel_id, Range::new(start, end)).await?;
+    tx.commit().await?;
+
+    Ok(merged)
+}
+
+// 여러 range를 한 번에 병합. 호출하는 쪽의 transaction 안에서 실행
+pub async fn add_sync_ranges_in(
+    conn: &mut SqliteConnection,
+    channel_id: i64,
+    new_ranges: &[Range],
+) -> Result<(), sqlx::Error> {
+    // batch 안에서 먼저 합쳐서 DB 조회 횟수를 줄임
+    let merged = new_ranges
+        .iter()
+        .fold(Vec::new(), |merged, range| merge_ranges(merged, *range));
+    for range in merged {
+        add_sync_range_in(conn, channel_id, range).await?;
+    }
+    Ok(())
+}
 
+// 새 range와 겹치거나 맞닿은 range만 찾아서 하나로 합침. 병합된 range를 반환
+pub async fn add_sync_range_in(
+    conn: &mut SqliteConnection,
+    channel_id: i64,
+    new_range: Range,
+) -> Result<Range, sqlx::Error> {
+    // touches 조건. (channel_id, start_id) PK와 (channel_id, end_id) index로 찾음
     let rows = sqlx::query(
-        "SELECT start_id, end_id FROM sync_ranges WHERE channel_id = ? ORDER BY start_id",
+        "SELECT start_id, end_id FROM sync_ranges
+         WHERE channel_id = ? AND start_id <= ? AND end_id >= ?",
     )
     .bind(channel_id)
-    .fetch_all(&mut *tx)
+    .bind(new_range.end.saturating_add(1))
+    .bind(new_range.start.saturating_sub(1))
+    .fetch_all(&mut *conn)
     .await?;
 
-    let existing_ranges: Vec<Range> = rows
-        .iter()
-        .map(|r| Range::new(r.try_get("start_id").unwrap(), r.try_get("end_id").unwrap()))
-        .collect();
-
-    let new_range = Range::new(start, end);
-    let merged_ranges = merge_ranges(existing_ranges, new_range);
+    let mut merged = new_range;
+    for row in &rows {
+        let existing = Range::new(row.try_get("start_id")?, row.try_get("end_id")?);
+        if existing.contains(&new_range) {
+            return Ok(existing); // 이미 캐시된 구간
+        }
+        merged = merged.merge(&existing).unwrap_or(merged);
+    }
 
-    sqlx::query("DELETE FROM sync_ranges WHERE channel_id = ?")
+    for row in &rows {
+        sqlx::query("DELETE FROM sync_ranges WHERE channel_id = ? AND start_id = ?")
+            .bind(channel_id)
+            .bind(row.try_get::<i64, _>("start_id")?)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("INSERT INTO sync_ranges (channel_id, start_id, end_id) VALUES (?, ?, ?)")
         .bind(channel_id)
-        .execute(&mut *tx)
+        .bind(merged.start)
+        .bind(merged.end)
+        .execute(&mut *conn)
         .await?;
 
-    for r in &merged_ranges {
-        sqlx::query("INSERT INTO sync_ranges (channel_id, start_id, end_id) VALUES (?, ?, ?)")
-            .bind(channel_id)
-            .bind(r.start)
-            .bind(r.end)
-            .execute(&mut *tx)
-            .await?;
+    Ok(merged)
+}
+
+// 설정 대상. category 설정도 채널 설정으로 저장
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SettingScope {
+    Guild(serenity::GuildId),
+    Channel(serenity::ChannelId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Setting {
+    Caching,
+    Revisions,
+    RetentionDays,
+    RetentionCount,
+    Bots,
+    BotAllowlist,
+    Redaction,
+    RedactionPatterns,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 8] = [
+        Setting::Caching,
+        Setting::Revisions,
+        Setting::RetentionDays,
+        Setting::RetentionCount,
+        Setting::Bots,
+        Setting::BotAllowlist,
+        Setting::Redaction,
+        Setting::RedactionPatterns,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Setting::Caching => "caching",
+            Setting::Revisions => "revisions",
+            Setting::RetentionDays => "retention_days",
+            Setting::RetentionCount => "retention_count",
+            Setting::Bots => "bots",
+            Setting::BotAllowlist => "bot_allowlist",
+            Setting::Redaction => "redaction",
+            Setting::RedactionPatterns => "redaction_patterns",
+        }
     }
 
-    tx.commit().await?;
+    fn parse(name: &str) -> Option<Self> {
+        Setting::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
 
-    // 방금 병합 했으니 겹치는거 하나는 있을 것임
-    let result_range = merged_ranges
-        .into_iter()
-        .find(|r| r.contains(&new_range))
-        .unwrap_or(new_range);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingValue {
+    Bool(bool),
+    Int(i64),
+    Text(String),
+    Ids(Vec<u64>),
+}
 
-    Ok(result_range)
+// 한 scope(서버 또는 채널)에 명시적으로 저장된 설정
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScopeSettings {
+    values: HashMap<Setting, SettingValue>,
 }
 
-pub async fn set_channel_caching(
-    pool: &SqlitePool,
-    channel_id: serenity::ChannelId,
-    enabled: bool,
-) -> Result<(), sqlx::Error> {
-    let key = format!("channel:{}:caching", channel_id);
-    let value = if enabled { "true" } else { "false" };
+impl ScopeSettings {
+    pub fn get(&self, setting: Setting) -> Option<&SettingValue> {
+        self.values.get(&setting)
+    }
 
-    sqlx::query("INSERT OR REPLACE INTO config (key, value) VALUES (?, ?)")
-        .bind(key)
-        .bind(value)
-        .execute(pool)
-        .await?;
+    fn bool(&self, setting: Setting) -> Option<bool> {
+        match self.get(setting) {
+            Some(SettingValue::Bool(value)) => Some(*value),
+            _ => None,
+        }
+    }
 
-    Ok(())
+    fn int(&self, setting: Setting) -> Option<i64> {
+        match self.get(setting) {
+            Some(SettingValue::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // None이면 상위 scope 설정을 따름
+    pub fn caching(&self) -> Option<bool> {
+        self.bool(Setting::Caching)
+    }
+
+    pub fn revisions(&self) -> bool {
+        self.bool(Setting::Revisions).unwrap_or(false)
+    }
+
+    pub fn retention(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_age_days: self.int(Setting::RetentionDays).filter(|v| *v > 0),
+            max_messages: self.int(Setting::RetentionCount).filter(|v| *v > 0),
+        }
+    }
+
+    pub fn bot_filter(&self) -> BotFilter {
+        BotFilter {
+            mode: match self.get(Setting::Bots) {
+                Some(SettingValue::Text(mode)) => BotCaching::parse(mode),
+                _ => BotCaching::default(),
+            },
+            allowlist: match self.get(Setting::BotAllowlist) {
+                Some(SettingValue::Ids(ids)) => ids.clone(),
+                _ => vec![],
+            },
+        }
+    }
+
+    // 감지기 이름은 쉼표로, 정규식은 줄바꿈으로 구분해서 저장
+    pub fn redaction(&self) -> RedactionRules {
+        RedactionRules {
+            detectors: match self.get(Setting::Redaction) {
+                Some(SettingValue::Text(names)) => {
+                    names.split(',').filter_map(Detector::parse).collect()
+                }
+                _ => vec![],
+            },
+            patterns: match self.get(Setting::RedactionPatterns) {
+                Some(SettingValue::Text(patterns)) => patterns
+                    .lines()
+                    .filter(|p| !p.is_empty())
+                    .map(str::to_owned)
+                    .collect(),
+                _ => vec![],
+            },
+        }
+    }
+}
+
+// settings 테이블 한 행. 저장소마다 같은 방식으로 읽고 씀
+pub type SettingRow = (String, Option<bool>, Option<i64>, Option<String>);
+
+impl ScopeSettings {
+    pub fn from_rows(rows: Vec<SettingRow>) -> Self {
+        let mut settings = ScopeSettings::default();
+        for (name, bool_value, int_value, text_value) in rows {
+            let Some(setting) = Setting::parse(&name) else {
+                continue; // 이후 버전에서 추가된 설정
+            };
+            let value = match setting {
+                Setting::Caching | Setting::Revisions => bool_value.map(SettingValue::Bool),
+                Setting::RetentionDays | Setting::RetentionCount => {
+                    int_value.map(SettingValue::Int)
+                }
+                Setting::Bots | Setting::Redaction | Setting::RedactionPatterns => {
+                    text_value.map(SettingValue::Text)
+                }
+                Setting::BotAllowlist => text_value.map(|ids| {
+                    SettingValue::Ids(
+                        ids.split(',')
+                            .filter_map(|id| id.trim().parse().ok())
+                            .collect(),
+                    )
+                }),
+            };
+            if let Some(value) = value {
+                settings.values.insert(setting, value);
+            }
+        }
+        settings
+    }
 }
 
-pub async fn is_channel_caching_enabled(
+impl SettingValue {
+    // (bool_value, int_value, text_value)
+    pub fn into_columns(self) -> (Option<bool>, Option<i64>, Option<String>) {
+        match self {
+            SettingValue::Bool(value) => (Some(value), None, None),
+            SettingValue::Int(value) => (None, Some(value), None),
+            SettingValue::Text(value) => (None, None, Some(value)),
+            SettingValue::Ids(ids) => (
+                None,
+                None,
+                Some(
+                    ids.iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+            ),
+        }
+    }
+}
+
+pub async fn load_settings(
     pool: &SqlitePool,
-    channel_id: serenity::ChannelId,
-) -> Result<bool, sqlx::Error> {
-    let key = format!("channel:{}:caching", channel_id);
+    scope: SettingScope,
+) -> Result<ScopeSettings, sqlx::Error> {
+    let rows = match scope {
+        SettingScope::Guild(guild_id) => sqlx::query_as(
+            "SELECT setting, bool_value, int_value, text_value FROM settings
+             WHERE guild_id = ? AND channel_id = 0",
+        )
+        .bind(guild_id.get() as i64),
+        SettingScope::Channel(channel_id) => sqlx::query_as(
+            "SELECT setting, bool_value, int_value, text_value FROM settings
+             WHERE channel_id = ?",
+        )
+        .bind(channel_id.get() as i64),
+    }
+    .fetch_all(pool)
+    .await?;
 
-    let row = sqlx::query("SELECT value FROM config WHERE key = ?")
-        .bind(key)
-        .fetch_optional(pool)
-        .await?;
+    Ok(ScopeSettings::from_rows(rows))
+}
+
+// value가 None이면 설정을 지워서 상위 scope나 기본값을 따르게 함
+// 채널 설정도 guild_id를 같이 저장해서 서버를 나갈 때 한 번에 지울 수 있게 함
+pub async fn save_setting(
+    pool: &SqlitePool,
+    guild_id: serenity::GuildId,
+    scope: SettingScope,
+    setting: Setting,
+    value: Option<SettingValue>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let channel_id = match scope {
+        SettingScope::Guild(_) => 0,
+        SettingScope::Channel(channel_id) => channel_id.get() as i64,
+    };
+    let guild_id = match scope {
+        SettingScope::Guild(guild_id) => guild_id,
+        SettingScope::Channel(_) => guild_id,
+    };
+
+    // 채널 설정은 서버를 모른 채로 옮겨진 것(guild_id 0)도 같이 지움
+    match scope {
+        SettingScope::Guild(_) => {
+            sqlx::query(
+                "DELETE FROM settings WHERE guild_id = ? AND channel_id = 0 AND setting = ?",
+            )
+            .bind(guild_id.get() as i64)
+        }
+        SettingScope::Channel(_) => {
+            sqlx::query("DELETE FROM settings WHERE channel_id = ? AND setting = ?")
+                .bind(channel_id)
+        }
+    }
+    .bind(setting.name())
+    .execute(&mut *tx)
+    .await?;
 
-    if let Some(row) = row {
-        let value: String = row.try_get("value")?;
-        Ok(value == "true")
-    } else {
-        Ok(false)
+    if let Some(value) = value {
+        let (bool_value, int_value, text_value) = value.into_columns();
+        sqlx::query(
+            "INSERT INTO settings (guild_id, channel_id, setting, bool_value, int_value, text_value)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(channel_id)
+        .bind(setting.name())
+        .bind(bool_value)
+        .bind(int_value)
+        .bind(text_value)
+        .execute(&mut *tx)
+        .await?;
     }
+
+    tx.commit().await?;
+    Ok(())
 }
 
 pub async fn set_version_subscription(
@@ -321,10 +783,12 @@ pub async fn should_notify_version(
     user_id: u64,
     latest_version: &str,
 ) -> Result<bool, sqlx::Error> {
-    let row = sqlx::query("SELECT last_notified_version FROM version_subscriptions WHERE user_id = ? AND enabled = 1")
-        .bind(user_id as i64)
-        .fetch_optional(pool)
-        .await?;
+    let row = sqlx::query(
+        "SELECT last_notified_version FROM version_subscriptions WHERE user_id = ? AND enabled = 1",
+    )
+    .bind(user_id as i64)
+    .fetch_optional(pool)
+    .await?;
 
     match row {
         Some(r) => {
@@ -335,16 +799,63 @@ pub async fn should_notify_version(
     }
 }
 
-pub async fn insert_message(pool: &SqlitePool, msg: &serenity::Message) -> Result<(), sqlx::Error> {
-    let guild_id = match msg.guild_id {
-        Some(id) => id.get() as i64,
-        None => return Ok(()), // Ignore DM messages for now
-    };
+pub async fn insert_messages(
+    pool: &SqlitePool,
+    msgs: &[serenity::Message],
+    guild_id: i64,
+    redactor: &Redactor,
+) -> Result<(), sqlx::Error> {
+    // 보통 한 채널의 메세지만 들어옴. transaction 열기 전에 설정 읽어둠
+    let channel_ids: Vec<_> = msgs.iter().map(|msg| msg.channel_id).collect();
+    let filters = load_bot_filters(pool, &channel_ids).await?;
+
+    let mut tx = pool.begin().await?;
+
+    for msg in msgs
+        .iter()
+        .filter(|msg| filters[&msg.channel_id].allows(msg))
+    {
+        insert_message_in(&mut tx, guild_id, msg, redactor).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// 채널별 봇 필터. single connection pool에서 transaction 중에 읽으면 막히므로 미리 읽어둠
+pub async fn load_bot_filters(
+    pool: &SqlitePool,
+    channel_ids: &[serenity::ChannelId],
+) -> Result<HashMap<serenity::ChannelId, BotFilter>, sqlx::Error> {
+    let mut filters = HashMap::new();
+    for channel_id in channel_ids {
+        if let Entry::Vacant(entry) = filters.entry(*channel_id) {
+            entry.insert(get_bot_filter(pool, *channel_id).await?);
+        }
+    }
+    Ok(filters)
+}
 
+// 서버별 가리기 규칙. 봇 필터와 같은 이유로 transaction 열기 전에 읽어둠
+// 봇에서는 Settings에 캐시된 것을 쓰고, 이건 아카이브와 CLI 가져오기처럼 한 번만 읽을 때
+pub async fn load_redactor(pool: &SqlitePool, guild_id: i64) -> Result<Redactor, sqlx::Error> {
+    let guild_id = serenity::GuildId::new(guild_id as u64);
+    let settings = load_settings(pool, SettingScope::Guild(guild_id)).await?;
+    Ok(Redactor::new(&settings.redaction()))
+}
+
+// 봇 필터는 호출하는 쪽에서 확인. 내용은 가린 뒤에 저장해서 messages_fts에도 가린 내용만 들어감
+pub async fn insert_message_in(
+    conn: &mut SqliteConnection,
+    guild_id: i64,
+    msg: &serenity::Message,
+    redactor: &Redactor,
+) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-        INSERT OR REPLACE INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?)
+        INSERT OR REPLACE INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at, reply_to_id)
+        SELECT ?, ?, ?, ?, ?, ?, ?, ?
+        WHERE NOT EXISTS (SELECT 1 FROM opted_out_users WHERE user_id = ?)
         "#
     )
     .bind(msg.id.get() as i64)
@@ -352,83 +863,145 @@ pub async fn insert_message(pool: &SqlitePool, msg: &serenity::Message) -> Resul
     .bind(guild_id)
     .bind(msg.author.id.get() as i64)
     .bind(&msg.author.name)
-    .bind(&msg.content)
+    .bind(redactor.redact(&msg.content))
     .bind(msg.timestamp.timestamp())
-    .execute(pool)
+    .bind(reply_to_id(msg))
+    .bind(msg.author.id.get() as i64)
+    .execute(&mut *conn)
     .await?;
 
+    upsert_member(conn, guild_id, msg).await?;
+
     Ok(())
 }
 
-pub async fn insert_messages(
-    pool: &SqlitePool,
-    msgs: &[serenity::Message],
+// 메세지에 붙어온 작성자 정보로 표시 이름 갱신
+// api로 가져온 메세지에는 member가 없으니 그때는 별명을 덮어쓰지 않음
+async fn upsert_member(
+    conn: &mut SqliteConnection,
     guild_id: i64,
+    msg: &serenity::Message,
 ) -> Result<(), sqlx::Error> {
-    let mut tx = pool.begin().await?;
+    if msg.webhook_id.is_some() {
+        return Ok(()); // 웹훅은 메세지마다 이름이 다름
+    }
 
-    for msg in msgs {
-        // let guild_id = match msg.guild_id {
-        //     Some(id) => id.get() as i64,
-        //     None => continue,
-        // };
+    let nick = msg.member.as_ref().map(|m| m.nick.clone());
+    sqlx::query(
+        r#"
+        INSERT INTO members (guild_id, user_id, username, global_name, nick, avatar_url, updated_at)
+        SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7
+        WHERE NOT EXISTS (SELECT 1 FROM opted_out_users WHERE user_id = ?2)
+        ON CONFLICT (guild_id, user_id) DO UPDATE SET
+            username = excluded.username,
+            global_name = excluded.global_name,
+            nick = CASE WHEN ?8 THEN excluded.nick ELSE members.nick END,
+            avatar_url = excluded.avatar_url,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(guild_id)
+    .bind(msg.author.id.get() as i64)
+    .bind(&msg.author.name)
+    .bind(&msg.author.global_name)
+    .bind(nick.clone().flatten())
+    .bind(msg.author.face())
+    .bind(chrono::Utc::now().timestamp())
+    .bind(nick.is_some())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+// 이미 캐시된 작성자만 갱신. 메세지가 없는 멤버까지 저장할 필요는 없음
+pub async fn update_member(
+    pool: &SqlitePool,
+    event: &serenity::GuildMemberUpdateEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE members
+        SET username = ?, global_name = ?, nick = ?, avatar_url = ?, updated_at = ?
+        WHERE guild_id = ? AND user_id = ?
+        "#,
+    )
+    .bind(&event.user.name)
+    .bind(&event.user.global_name)
+    .bind(&event.nick)
+    .bind(event.user.face())
+    .bind(chrono::Utc::now().timestamp())
+    .bind(event.guild_id.get() as i64)
+    .bind(event.user.id.get() as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
 
+pub async fn update_message_in(
+    conn: &mut SqliteConnection,
+    event: &serenity::MessageUpdateEvent,
+    keep_revision: bool,
+    redactor: &Redactor,
+) -> Result<(), sqlx::Error> {
+    let Some(content) = &event.content else {
+        return Ok(());
+    };
+    // 이전 내용은 저장할 때 이미 가려졌으니 비교도 가린 내용으로
+    let content = redactor.redact(content);
+    let message_id = event.id.get() as i64;
+
+    if keep_revision {
+        let edited_at = event
+            .edited_timestamp
+            .map(|t| t.unix_timestamp())
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+        // 내용이 바뀐 경우만 이전 내용을 기록 (임베드 로딩 등으로도 update 이벤트가 옴)
         sqlx::query(
             r#"
-            INSERT OR REPLACE INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
-            "#
+            INSERT INTO message_revisions (message_id, channel_id, guild_id, content, edited_at)
+            SELECT message_id, channel_id, guild_id, content, ?
+            FROM messages
+            WHERE message_id = ? AND content != ?
+            "#,
         )
-        .bind(msg.id.get() as i64)
-        .bind(msg.channel_id.get() as i64)
-        .bind(guild_id)
-        .bind(msg.author.id.get() as i64)
-        .bind(&msg.author.name)
-        .bind(&msg.content)
-        .bind(msg.timestamp.timestamp())
-        .execute(&mut *tx)
+        .bind(edited_at)
+        .bind(message_id)
+        .bind(content.as_ref())
+        .execute(&mut *conn)
         .await?;
     }
 
-    tx.commit().await?;
+    sqlx::query("UPDATE messages SET content = ? WHERE message_id = ?")
+        .bind(content.as_ref())
+        .bind(message_id)
+        .execute(&mut *conn)
+        .await?;
+
     Ok(())
 }
 
-pub async fn update_message(
+pub async fn delete_message(
     pool: &SqlitePool,
-    event: &serenity::MessageUpdateEvent,
+    message_id: serenity::MessageId,
 ) -> Result<(), sqlx::Error> {
-    if let Some(content) = &event.content {
-        sqlx::query("UPDATE messages SET content = ? WHERE message_id = ?")
-            .bind(content)
-            .bind(event.id.get() as i64)
-            .execute(pool)
-            .await?;
-    }
-    Ok(())
+    let mut conn = pool.acquire().await?;
+    delete_message_in(&mut conn, message_id).await
 }
 
-pub async fn delete_message(
-    pool: &SqlitePool,
+pub async fn delete_message_in(
+    conn: &mut SqliteConnection,
     message_id: serenity::MessageId,
 ) -> Result<(), sqlx::Error> {
     sqlx::query("DELETE FROM messages WHERE message_id = ?")
         .bind(message_id.get() as i64)
-        .execute(pool)
+        .execute(conn)
         .await?;
     Ok(())
 }
 
-pub async fn delete_messages(
-    pool: &SqlitePool,
-    message_ids: &[serenity::MessageId],
-) -> Result<(), sqlx::Error> {
-    for id in message_ids {
-        delete_message(pool, *id).await?;
-    }
-    Ok(())
-}
-
 pub async fn delete_channel_messages(
     pool: &SqlitePool,
     channel_id: serenity::ChannelId,
@@ -466,8 +1039,10 @@ pub async fn search_messages_fts(
 
     sqlx::query_as::<_, SearchResult>(
         r#"
-        SELECT m.message_id, m.channel_id, m.guild_id, m.author_id, m.author_name, m.content, m.created_at
+        SELECT m.message_id, m.channel_id, m.guild_id, m.author_id, m.author_name, m.content, m.created_at, m.reply_to_id,
+               COALESCE(mem.nick, mem.global_name) AS display_name, mem.avatar_url
         FROM messages m
+        LEFT JOIN members mem ON mem.guild_id = m.guild_id AND mem.user_id = m.author_id
         JOIN messages_fts f ON m.message_id = f.rowid
         WHERE m.guild_id = ?
           AND m.channel_id = ?
@@ -485,6 +1060,7 @@ pub async fn search_messages_fts(
     .await
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn search_messages_range(
     pool: &SqlitePool,
     guild_id: i64,
@@ -493,18 +1069,27 @@ pub async fn search_messages_range(
     min_id: i64,
     max_id: i64,
     limit: u32,
+    include_revisions: bool,
 ) -> Result<Vec<SearchResult>, sqlx::Error> {
     let query_pattern = format!("%{}%", query);
 
     sqlx::query_as::<_, SearchResult>(
         r#"
-        SELECT m.message_id, m.channel_id, m.guild_id, m.author_id, m.author_name, m.content, m.created_at
+        SELECT m.message_id, m.channel_id, m.guild_id, m.author_id, m.author_name, m.content, m.created_at, m.reply_to_id,
+               COALESCE(mem.nick, mem.global_name) AS display_name, mem.avatar_url
         FROM messages m
-        WHERE m.guild_id = ? 
-          AND m.channel_id = ? 
-          AND m.message_id >= ? 
+        LEFT JOIN members mem ON mem.guild_id = m.guild_id AND mem.user_id = m.author_id
+        WHERE m.guild_id = ?
+          AND m.channel_id = ?
+          AND m.message_id >= ?
           AND m.message_id <= ?
-          AND content LIKE ?
+          AND (
+            m.content LIKE ?
+            OR (? AND m.message_id IN (
+                SELECT r.message_id FROM message_revisions r
+                WHERE r.channel_id = m.channel_id AND r.content LIKE ?
+            ))
+          )
         ORDER BY m.message_id DESC
         LIMIT ?
         "#,
@@ -513,8 +1098,1672 @@ pub async fn search_messages_range(
     .bind(channel_id)
     .bind(min_id)
     .bind(max_id)
-    .bind(query_pattern)
+    .bind(&query_pattern)
+    .bind(include_revisions)
+    .bind(&query_pattern)
     .bind(limit)
     .fetch_all(pool)
     .await
 }
+
+pub async fn get_message(
+    pool: &SqlitePool,
+    message_id: i64,
+) -> Result<Option<SearchResult>, sqlx::Error> {
+    sqlx::query_as::<_, SearchResult>(
+        r#"
+        SELECT m.message_id, m.channel_id, m.guild_id, m.author_id, m.author_name, m.content, m.created_at, m.reply_to_id,
+               COALESCE(mem.nick, mem.global_name) AS display_name, mem.avatar_url
+        FROM messages m
+        LEFT JOIN members mem ON mem.guild_id = m.guild_id AND mem.user_id = m.author_id
+        WHERE m.message_id = ?
+        "#,
+    )
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_replies(
+    pool: &SqlitePool,
+    message_id: i64,
+    limit: u32,
+) -> Result<Vec<SearchResult>, sqlx::Error> {
+    sqlx::query_as::<_, SearchResult>(
+        r#"
+        SELECT m.message_id, m.channel_id, m.guild_id, m.author_id, m.author_name, m.content, m.created_at, m.reply_to_id,
+               COALESCE(mem.nick, mem.global_name) AS display_name, mem.avatar_url
+        FROM messages m
+        LEFT JOIN members mem ON mem.guild_id = m.guild_id AND mem.user_id = m.author_id
+        WHERE m.reply_to_id = ?
+        ORDER BY m.message_id
+        LIMIT ?
+        "#,
+    )
+    .bind(message_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackfillStatus {
+    Running,
+    Paused,
+    Done,
+}
+
+impl BackfillStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackfillStatus::Running => "running",
+            BackfillStatus::Paused => "paused",
+            BackfillStatus::Done => "done",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "paused" => BackfillStatus::Paused,
+            "done" => BackfillStatus::Done,
+            _ => BackfillStatus::Running,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BackfillState {
+    pub channel_id: i64,
+    pub guild_id: i64,
+    pub cursor_id: Option<i64>,
+    pub status: BackfillStatus,
+}
+
+impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for BackfillState {
+    fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let status: String = row.try_get("status")?;
+        Ok(Self {
+            channel_id: row.try_get("channel_id")?,
+            guild_id: row.try_get("guild_id")?,
+            cursor_id: row.try_get("cursor_id")?,
+            status: BackfillStatus::parse(&status),
+        })
+    }
+}
+
+// 이미 있으면 진행 상태 유지
+pub async fn enable_backfill(
+    pool: &SqlitePool,
+    guild_id: serenity::GuildId,
+    channel_id: serenity::ChannelId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO backfill_state (channel_id, guild_id, cursor_id, status, updated_at) VALUES (?, ?, NULL, ?, ?)
+         ON CONFLICT(channel_id) DO NOTHING",
+    )
+    .bind(channel_id.get() as i64)
+    .bind(guild_id.get() as i64)
+    .bind(BackfillStatus::Running.as_str())
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_backfill_state(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<Option<BackfillState>, sqlx::Error> {
+    sqlx::query_as::<_, BackfillState>(
+        "SELECT channel_id, guild_id, cursor_id, status FROM backfill_state WHERE channel_id = ?",
+    )
+    .bind(channel_id.get() as i64)
+    .fetch_optional(pool)
+    .await
+}
+
+// 진행 중인 채널을 돌아가면서 처리하도록 가장 오래 전에 처리한 채널 선택
+pub async fn next_backfill_channel(
+    pool: &SqlitePool,
+) -> Result<Option<BackfillState>, sqlx::Error> {
+    sqlx::query_as::<_, BackfillState>(
+        "SELECT channel_id, guild_id, cursor_id, status FROM backfill_state
+         WHERE status = ? ORDER BY updated_at LIMIT 1",
+    )
+    .bind(BackfillStatus::Running.as_str())
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_backfill_cursor(
+    pool: &SqlitePool,
+    channel_id: i64,
+    cursor_id: Option<i64>,
+    status: BackfillStatus,
+) -> Result<(), sqlx::Error> {
+    // 처리 도중 pause 된 경우 덮어쓰지 않음
+    sqlx::query(
+        "UPDATE backfill_state SET cursor_id = ?, updated_at = ?,
+             status = CASE WHEN status = ? THEN status ELSE ? END
+         WHERE channel_id = ?",
+    )
+    .bind(cursor_id)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(BackfillStatus::Paused.as_str())
+    .bind(status.as_str())
+    .bind(channel_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_backfill_status(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+    status: BackfillStatus,
+) -> Result<bool, sqlx::Error> {
+    let result =
+        sqlx::query("UPDATE backfill_state SET status = ?, updated_at = ? WHERE channel_id = ?")
+            .bind(status.as_str())
+            .bind(chrono::Utc::now().timestamp())
+            .bind(channel_id.get() as i64)
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_backfill_state(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM backfill_state WHERE channel_id = ?")
+        .bind(channel_id.get() as i64)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// id를 포함하는 sync range
+pub async fn find_sync_range(
+    pool: &SqlitePool,
+    channel_id: i64,
+    message_id: i64,
+) -> Result<Option<Range>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT start_id, end_id FROM sync_ranges
+         WHERE channel_id = ? AND start_id <= ? AND end_id >= ?
+         ORDER BY start_id DESC LIMIT 1",
+    )
+    .bind(channel_id)
+    .bind(message_id)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(r) => Ok(Some(Range::new(
+            r.try_get("start_id")?,
+            r.try_get("end_id")?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+pub async fn count_channel_messages(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query("SELECT COUNT(*) AS count FROM messages WHERE channel_id = ?")
+        .bind(channel_id.get() as i64)
+        .fetch_one(pool)
+        .await?;
+    row.try_get("count")
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub count: i64,
+    // 캐시된 메세지 중 가장 이른/늦은 작성 시각 (unix 초)
+    pub earliest: Option<i64>,
+    pub latest: Option<i64>,
+}
+
+pub async fn channel_cache_stats(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<CacheStats, sqlx::Error> {
+    let (count, earliest, latest) = sqlx::query_as(
+        "SELECT COUNT(*), MIN(created_at), MAX(created_at) FROM messages WHERE channel_id = ?",
+    )
+    .bind(channel_id.get() as i64)
+    .fetch_one(pool)
+    .await?;
+    Ok(CacheStats {
+        count,
+        earliest,
+        latest,
+    })
+}
+
+// DB 파일 크기 (byte). WAL 파일은 포함하지 않음
+pub async fn database_size(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// 캐시된 데이터가 있는 채널. 캐싱이 꺼지면 데이터가 지워지므로 캐싱 중인 채널과 같음
+// 서버, 카테고리 단위 설정은 discord 채널 정보 없이는 풀 수 없어서 설정 대신 데이터 기준
+pub async fn list_caching_channels(
+    pool: &SqlitePool,
+) -> Result<Vec<serenity::ChannelId>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT channel_id FROM sync_ranges
+         UNION SELECT channel_id FROM backfill_state",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let id: i64 = row.try_get("channel_id")?;
+            Ok(serenity::ChannelId::new(id as u64))
+        })
+        .collect()
+}
+
+pub async fn list_guild_cached_channels(
+    pool: &SqlitePool,
+    guild_id: serenity::GuildId,
+) -> Result<Vec<serenity::ChannelId>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT channel_id FROM messages WHERE guild_id = ?
+         UNION SELECT channel_id FROM backfill_state WHERE guild_id = ?",
+    )
+    .bind(guild_id.get() as i64)
+    .bind(guild_id.get() as i64)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let id: i64 = row.try_get("channel_id")?;
+            Ok(serenity::ChannelId::new(id as u64))
+        })
+        .collect()
+}
+
+// 채널의 캐시 데이터 전부 삭제
+pub async fn purge_channel(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<(), sqlx::Error> {
+    delete_channel_messages(pool, channel_id).await?;
+    delete_channel_sync_ranges(pool, channel_id).await?;
+    delete_backfill_state(pool, channel_id).await?;
+    Ok(())
+}
+
+// 채널이 삭제되면 설정까지 전부 삭제
+pub async fn forget_channel(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<(), sqlx::Error> {
+    let id = channel_id.get() as i64;
+    let mut tx = pool.begin().await?;
+
+    for sql in [
+        "DELETE FROM messages WHERE channel_id = ?",
+        "DELETE FROM message_revisions WHERE channel_id = ?",
+        "DELETE FROM sync_ranges WHERE channel_id = ?",
+        "DELETE FROM backfill_state WHERE channel_id = ?",
+    ] {
+        sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+    }
+    sqlx::query("DELETE FROM settings WHERE channel_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// 서버에서 나가면 그 서버의 데이터 전부 삭제
+// sync range와 채널 설정에는 guild id가 없어서 캐시된 데이터로 채널을 찾고, 그 외 채널은 인자로 받음
+pub async fn forget_guild(
+    pool: &SqlitePool,
+    guild_id: serenity::GuildId,
+    known_channels: &[serenity::ChannelId],
+) -> Result<(), sqlx::Error> {
+    let mut channels = list_guild_cached_channels(pool, guild_id).await?;
+    channels.extend_from_slice(known_channels);
+    channels.sort();
+    channels.dedup();
+    for channel_id in channels {
+        forget_channel(pool, channel_id).await?;
+    }
+
+    let id = guild_id.get() as i64;
+    let mut tx = pool.begin().await?;
+    for sql in [
+        "DELETE FROM messages WHERE guild_id = ?",
+        "DELETE FROM message_revisions WHERE guild_id = ?",
+        "DELETE FROM backfill_state WHERE guild_id = ?",
+        "DELETE FROM members WHERE guild_id = ?",
+        "DELETE FROM settings WHERE guild_id = ?",
+    ] {
+        sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// 데이터가 남아있는 서버 목록. DM(guild id 0)은 제외
+pub async fn list_known_guilds(pool: &SqlitePool) -> Result<Vec<serenity::GuildId>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT guild_id FROM messages
+         UNION SELECT guild_id FROM message_revisions
+         UNION SELECT guild_id FROM backfill_state
+         UNION SELECT guild_id FROM members
+         UNION SELECT guild_id FROM settings",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut guilds = Vec::new();
+    for row in rows {
+        let id: i64 = row.try_get("guild_id")?;
+        if id > 0 {
+            guilds.push(serenity::GuildId::new(id as u64));
+        }
+    }
+
+    guilds.sort();
+    guilds.dedup();
+    Ok(guilds)
+}
+
+// 가장 최근 메세지를 포함하는 sync range
+pub async fn latest_sync_range(
+    pool: &SqlitePool,
+    channel_id: i64,
+) -> Result<Option<Range>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT start_id, end_id FROM sync_ranges WHERE channel_id = ? ORDER BY end_id DESC LIMIT 1",
+    )
+    .bind(channel_id)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(r) => Ok(Some(Range::new(
+            r.try_get("start_id")?,
+            r.try_get("end_id")?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+pub async fn list_message_ids_range(
+    pool: &SqlitePool,
+    channel_id: i64,
+    min_id: i64,
+    max_id: i64,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT message_id FROM messages WHERE channel_id = ? AND message_id >= ? AND message_id <= ?",
+    )
+    .bind(channel_id)
+    .bind(min_id)
+    .bind(max_id)
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| row.try_get("message_id"))
+        .collect()
+}
+
+// 채널 아카이브에 들어가는 메세지. 채널, 서버 id는 아카이브 헤더에 한 번만 기록
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ArchivedMessage {
+    pub message_id: i64,
+    pub author_id: i64,
+    pub author_name: String,
+    pub content: String,
+    pub created_at: i64,
+    pub reply_to_id: Option<i64>,
+}
+
+impl ArchivedMessage {
+    pub fn from_message(msg: &serenity::Message) -> Self {
+        Self {
+            message_id: msg.id.get() as i64,
+            author_id: msg.author.id.get() as i64,
+            author_name: msg.author.name.clone(),
+            content: msg.content.clone(),
+            created_at: msg.timestamp.timestamp(),
+            reply_to_id: reply_to_id(msg),
+        }
+    }
+}
+
+// after_id보다 큰 id부터 오래된 순서로. 채널 전체를 한 번에 읽지 않도록 나눠서 가져옴
+pub async fn list_archived_messages(
+    pool: &SqlitePool,
+    channel_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<ArchivedMessage>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT message_id, author_id, author_name, content, created_at, reply_to_id
+         FROM messages WHERE channel_id = ? AND message_id > ?
+         ORDER BY message_id LIMIT ?",
+    )
+    .bind(channel_id)
+    .bind(after_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+// 이미 캐시된 메세지는 더 최신일 수 있으니 그대로 둠. 캐싱을 거부한 사용자의 메세지는 건너뜀
+// 새로 들어간 개수를 반환
+pub async fn insert_archived_messages_in(
+    conn: &mut SqliteConnection,
+    guild_id: i64,
+    channel_id: i64,
+    msgs: &[ArchivedMessage],
+    redactor: &Redactor,
+) -> Result<u64, sqlx::Error> {
+    let mut inserted = 0;
+    for msg in msgs {
+        inserted += sqlx::query(
+            "INSERT INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at, reply_to_id)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+             WHERE NOT EXISTS (SELECT 1 FROM opted_out_users WHERE user_id = ?4)
+             ON CONFLICT (message_id) DO NOTHING",
+        )
+        .bind(msg.message_id)
+        .bind(channel_id)
+        .bind(guild_id)
+        .bind(msg.author_id)
+        .bind(&msg.author_name)
+        .bind(redactor.redact(&msg.content))
+        .bind(msg.created_at)
+        .bind(msg.reply_to_id)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    }
+    Ok(inserted)
+}
+
+// 외부 덤프에서 가져온 메세지. insert_messages와 같은 봇 필터를 거치지만 이미 캐시된 메세지는 그대로 둠
+// 덤프의 작성자 정보로는 프로필 사진을 알 수 없어서 members는 갱신하지 않음
+pub async fn import_messages(
+    pool: &SqlitePool,
+    msgs: &[serenity::Message],
+    guild_id: i64,
+    redactor: &Redactor,
+) -> Result<u64, sqlx::Error> {
+    let channel_ids: Vec<_> = msgs.iter().map(|msg| msg.channel_id).collect();
+    let filters = load_bot_filters(pool, &channel_ids).await?;
+
+    let mut tx = pool.begin().await?;
+    let mut inserted = 0;
+    for msg in msgs
+        .iter()
+        .filter(|msg| filters[&msg.channel_id].allows(msg))
+    {
+        inserted += insert_archived_messages_in(
+            &mut tx,
+            guild_id,
+            msg.channel_id.get() as i64,
+            &[ArchivedMessage::from_message(msg)],
+            redactor,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(inserted)
+}
+
+pub async fn list_sync_ranges(
+    pool: &SqlitePool,
+    channel_id: i64,
+) -> Result<Vec<Range>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT start_id, end_id FROM sync_ranges WHERE channel_id = ? ORDER BY start_id",
+    )
+    .bind(channel_id)
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| Ok(Range::new(row.try_get("start_id")?, row.try_get("end_id")?)))
+        .collect()
+}
+
+// 사용자의 캐시된 메세지와 표시 이름을 모든 서버에서 지우고, 이후 메세지도 저장하지 않도록 기록
+// 메세지가 지워지면 수정 기록은 trigger로 같이 지워짐. 지운 메세지 수를 반환
+pub async fn forget_user(pool: &SqlitePool, user_id: serenity::UserId) -> Result<u64, sqlx::Error> {
+    let id = user_id.get() as i64;
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("INSERT OR IGNORE INTO opted_out_users (user_id, opted_out_at) VALUES (?, ?)")
+        .bind(id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+    let deleted = sqlx::query("DELETE FROM messages WHERE author_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query("DELETE FROM members WHERE user_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+// 주어진 사용자 중 캐싱을 거부한 사용자
+pub async fn list_opted_out_users(
+    pool: &SqlitePool,
+    user_ids: &[i64],
+) -> Result<HashSet<i64>, sqlx::Error> {
+    if user_ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let placeholders = vec!["?"; user_ids.len()].join(", ");
+    let sql = format!("SELECT user_id FROM opted_out_users WHERE user_id IN ({placeholders})");
+    let mut query = sqlx::query(&sql);
+    for id in user_ids {
+        query = query.bind(id);
+    }
+
+    let rows = query.fetch_all(pool).await?;
+    rows.into_iter().map(|row| row.try_get("user_id")).collect()
+}
+
+// /privacy my-data로 보내는 사용자 데이터. 사용자 id로 찾을 수 있는 것 전부
+#[derive(Debug, serde::Serialize)]
+pub struct UserData {
+    pub user_id: i64,
+    pub exported_at: i64,
+    pub opted_out_at: Option<i64>,
+    pub version_subscription: Option<VersionSubscription>,
+    pub members: Vec<UserMember>,
+    pub messages: Vec<UserMessage>,
+}
+
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct VersionSubscription {
+    pub enabled: bool,
+    pub last_notified_version: Option<String>,
+}
+
+// 서버별 표시 이름
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct UserMember {
+    pub guild_id: i64,
+    pub username: String,
+    pub global_name: Option<String>,
+    pub nick: Option<String>,
+    pub avatar_url: Option<String>,
+    pub updated_at: i64,
+}
+
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct UserMessage {
+    pub message_id: i64,
+    pub channel_id: i64,
+    pub guild_id: i64,
+    pub author_name: String,
+    pub content: String,
+    pub created_at: i64,
+    pub reply_to_id: Option<i64>,
+    #[sqlx(skip)]
+    pub revisions: Vec<UserRevision>,
+}
+
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct UserRevision {
+    pub message_id: i64,
+    pub content: String,
+    pub edited_at: i64,
+}
+
+pub async fn collect_user_data(
+    pool: &SqlitePool,
+    user_id: serenity::UserId,
+) -> Result<UserData, sqlx::Error> {
+    let id = user_id.get() as i64;
+
+    let opted_out_at =
+        sqlx::query_scalar("SELECT opted_out_at FROM opted_out_users WHERE user_id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+    let version_subscription = sqlx::query_as(
+        "SELECT enabled, last_notified_version FROM version_subscriptions WHERE user_id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let members = sqlx::query_as(
+        "SELECT guild_id, username, global_name, nick, avatar_url, updated_at
+         FROM members WHERE user_id = ? ORDER BY guild_id",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    let mut messages: Vec<UserMessage> = sqlx::query_as(
+        "SELECT message_id, channel_id, guild_id, author_name, content, created_at, reply_to_id
+         FROM messages WHERE author_id = ? ORDER BY message_id",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    let revisions: Vec<UserRevision> = sqlx::query_as(
+        "SELECT r.message_id, r.content, r.edited_at
+         FROM message_revisions r JOIN messages m ON m.message_id = r.message_id
+         WHERE m.author_id = ? ORDER BY r.message_id, r.edited_at",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    let mut by_message: HashMap<i64, Vec<UserRevision>> = HashMap::new();
+    for revision in revisions {
+        by_message.entry(revision.message_id).or_default().push(revision);
+    }
+    for message in &mut messages {
+        message.revisions = by_message.remove(&message.message_id).unwrap_or_default();
+    }
+
+    Ok(UserData {
+        user_id: id,
+        exported_at: chrono::Utc::now().timestamp(),
+        opted_out_at,
+        version_subscription,
+        members,
+        messages,
+    })
+}
+
+pub async fn delete_guild_revisions(
+    pool: &SqlitePool,
+    guild_id: serenity::GuildId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM message_revisions WHERE guild_id = ?")
+        .bind(guild_id.get() as i64)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// 규칙을 추가했을 때 이미 캐시된 메세지와 수정 기록에도 적용. 바뀐 행 수를 반환
+// 서버 전체를 한 번에 읽지 않도록 나눠서 처리
+pub async fn redact_guild_messages(
+    pool: &SqlitePool,
+    guild_id: serenity::GuildId,
+    redactor: &Redactor,
+) -> Result<u64, sqlx::Error> {
+    const PAGE_SIZE: i64 = 1_000;
+    if redactor.is_empty() {
+        return Ok(0);
+    }
+
+    let mut changed = 0;
+    for (select, update) in [
+        (
+            "SELECT message_id, content FROM messages
+             WHERE guild_id = ? AND message_id > ? ORDER BY message_id LIMIT ?",
+            "UPDATE messages SET content = ? WHERE message_id = ?",
+        ),
+        (
+            "SELECT revision_id, content FROM message_revisions
+             WHERE guild_id = ? AND revision_id > ? ORDER BY revision_id LIMIT ?",
+            "UPDATE message_revisions SET content = ? WHERE revision_id = ?",
+        ),
+    ] {
+        let mut after_id = 0;
+        loop {
+            let page: Vec<(i64, String)> = sqlx::query_as(select)
+                .bind(guild_id.get() as i64)
+                .bind(after_id)
+                .bind(PAGE_SIZE)
+                .fetch_all(pool)
+                .await?;
+            let Some((last_id, _)) = page.last() else {
+                break;
+            };
+            after_id = *last_id;
+
+            let mut tx = pool.begin().await?;
+            for (id, content) in &page {
+                if let Cow::Owned(redacted) = redactor.redact(content) {
+                    sqlx::query(update)
+                        .bind(redacted)
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                    changed += 1;
+                }
+            }
+            tx.commit().await?;
+        }
+    }
+    Ok(changed)
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct MessageRevision {
+    pub content: String,
+    pub edited_at: i64,
+}
+
+// 오래된 것부터
+pub async fn list_message_revisions(
+    pool: &SqlitePool,
+    message_id: i64,
+) -> Result<Vec<MessageRevision>, sqlx::Error> {
+    sqlx::query_as::<_, MessageRevision>(
+        "SELECT content, edited_at FROM message_revisions WHERE message_id = ? ORDER BY revision_id",
+    )
+    .bind(message_id)
+    .fetch_all(pool)
+    .await
+}
+
+// 수정 기록이 있는 메세지만 골라냄
+pub async fn messages_with_revisions(
+    pool: &SqlitePool,
+    message_ids: &[i64],
+) -> Result<Vec<i64>, sqlx::Error> {
+    if message_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = vec!["?"; message_ids.len()].join(", ");
+    let sql = format!(
+        "SELECT DISTINCT message_id FROM message_revisions WHERE message_id IN ({placeholders})"
+    );
+    let mut query = sqlx::query(&sql);
+    for id in message_ids {
+        query = query.bind(id);
+    }
+
+    let rows = query.fetch_all(pool).await?;
+    rows.into_iter()
+        .map(|row| row.try_get("message_id"))
+        .collect()
+}
+
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+// 해당 시각에 만들어진 메세지보다 작거나 같은 가장 큰 snowflake
+pub fn snowflake_from_timestamp(unix_secs: i64) -> i64 {
+    (unix_secs * 1000 - DISCORD_EPOCH_MS).max(0) << 22
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub max_age_days: Option<i64>,
+    pub max_messages: Option<i64>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.max_age_days.is_none() && self.max_messages.is_none()
+    }
+
+    // 채널 설정이 없는 항목은 서버 설정을 따름
+    pub fn or(self, fallback: RetentionPolicy) -> RetentionPolicy {
+        RetentionPolicy {
+            max_age_days: self.max_age_days.or(fallback.max_age_days),
+            max_messages: self.max_messages.or(fallback.max_messages),
+        }
+    }
+
+    // 보관 기간을 최대 max_age_days로 제한. 설정이 없어도 max_age_days는 적용
+    pub fn capped(self, max_age_days: i64) -> RetentionPolicy {
+        RetentionPolicy {
+            max_age_days: Some(self.max_age_days.map_or(max_age_days, |d| d.min(max_age_days))),
+            max_messages: self.max_messages,
+        }
+    }
+
+    // 채널 정책에 서버 정책과 retention.max_age_days 상한을 적용한 실제 정책
+    pub fn effective(self, guild: RetentionPolicy) -> RetentionPolicy {
+        self.or(guild)
+            .capped(crate::config::get().retention.max_age_days)
+    }
+
+    // 이 id보다 오래된 메세지는 보관 기간이 지남
+    pub fn age_cutoff_id(&self, now: i64) -> Option<i64> {
+        self.max_age_days
+            .map(|days| snowflake_from_timestamp(now - days * 86_400))
+    }
+}
+
+// 채널 설정이 없는 항목은 서버 설정을 따르고, 보관 기간은 retention.max_age_days를 넘지 않음
+pub async fn effective_retention(
+    pool: &SqlitePool,
+    guild_id: serenity::GuildId,
+    channel_id: serenity::ChannelId,
+) -> Result<RetentionPolicy, sqlx::Error> {
+    let channel = load_settings(pool, SettingScope::Channel(channel_id)).await?;
+    let guild = load_settings(pool, SettingScope::Guild(guild_id)).await?;
+    Ok(channel.retention().effective(guild.retention()))
+}
+
+pub async fn channel_guild_id(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<Option<serenity::GuildId>, sqlx::Error> {
+    let row = sqlx::query("SELECT guild_id FROM messages WHERE channel_id = ? LIMIT 1")
+        .bind(channel_id.get() as i64)
+        .fetch_optional(pool)
+        .await?;
+
+    match row {
+        Some(r) => Ok(Some(serenity::GuildId::new(
+            r.try_get::<i64, _>("guild_id")? as u64,
+        ))),
+        None => Ok(None),
+    }
+}
+
+// 최신 n번째 메세지 id. 이보다 오래된 건 개수 제한 초과
+pub async fn nth_newest_message_id(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+    n: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT message_id FROM messages WHERE channel_id = ? ORDER BY message_id DESC LIMIT 1 OFFSET ?",
+    )
+    .bind(channel_id.get() as i64)
+    .bind(n - 1)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(r) => Ok(Some(r.try_get("message_id")?)),
+        None => Ok(None),
+    }
+}
+
+// min_id보다 오래된 메세지를 지우고 sync range도 min_id부터 시작하도록 줄임
+// 지운 구간을 캐시된 것처럼 남겨두면 검색이 api로 다시 채우지 않음
+pub async fn prune_channel_before(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+    min_id: i64,
+) -> Result<u64, sqlx::Error> {
+    let channel_id = channel_id.get() as i64;
+    let mut tx = pool.begin().await?;
+
+    let deleted = sqlx::query("DELETE FROM messages WHERE channel_id = ? AND message_id < ?")
+        .bind(channel_id)
+        .bind(min_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    sqlx::query("DELETE FROM sync_ranges WHERE channel_id = ? AND end_id < ?")
+        .bind(channel_id)
+        .bind(min_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE sync_ranges SET start_id = ? WHERE channel_id = ? AND start_id < ?")
+        .bind(min_id)
+        .bind(channel_id)
+        .bind(min_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+// 지우고 고치면서 쌓인 FTS 색인 segment를 하나로 합침
+pub async fn optimize_fts(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO messages_fts(messages_fts) VALUES ('optimize')")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// 문제가 없으면 빈 목록
+pub async fn integrity_check(pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().filter(|row| row != "ok").collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vacuum {
+    Incremental,
+    Full,
+    Skipped,
+}
+
+// auto_vacuum이 incremental이면 빈 페이지만 돌려주고, 아니면 빈 페이지가 전체의
+// min_free_ratio 이상일 때만 VACUUM. VACUUM은 파일 전체를 다시 쓰고 그동안 쓰기를 막음
+pub async fn vacuum(pool: &SqlitePool, min_free_ratio: f64) -> Result<Vacuum, sqlx::Error> {
+    let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum")
+        .fetch_one(pool)
+        .await?;
+    if auto_vacuum == 2 {
+        sqlx::query("PRAGMA incremental_vacuum").execute(pool).await?;
+        return Ok(Vacuum::Incremental);
+    }
+
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(pool)
+        .await?;
+    let freelist_count: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(pool)
+        .await?;
+    if page_count == 0 || (freelist_count as f64) < page_count as f64 * min_free_ratio {
+        return Ok(Vacuum::Skipped);
+    }
+    sqlx::query("VACUUM").execute(pool).await?;
+    Ok(Vacuum::Full)
+}
+
+pub async fn analyze(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("ANALYZE").execute(pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) async fn memory_pool() -> SqlitePool {
+    // in-memory db는 connection마다 따로 생기므로 하나만 씀
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
+
+#[cfg(test)]
+mod retention_tests {
+    use super::*;
+
+    async fn insert_row(pool: &SqlitePool, message_id: i64, content: &str) {
+        sqlx::query(
+            "INSERT INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at)
+             VALUES (?, 1, 1, 1, 'author', ?, 0)",
+        )
+        .bind(message_id)
+        .bind(content)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn snowflake_from_timestamp_matches_discord_epoch() {
+        assert_eq!(snowflake_from_timestamp(1_420_070_400), 0);
+        // 데모 봇 application id (snowflake)
+        let id = 1032354931673407620_i64;
+        let created_at = serenity::MessageId::new(id as u64)
+            .created_at()
+            .unix_timestamp();
+        assert!(snowflake_from_timestamp(created_at) <= id);
+        assert!(snowflake_from_timestamp(created_at + 1) > id);
+    }
+
+    #[test]
+    fn channel_retention_overrides_guild() {
+        let channel = RetentionPolicy {
+            max_age_days: Some(30),
+            max_messages: None,
+        };
+        let guild = RetentionPolicy {
+            max_age_days: Some(180),
+            max_messages: Some(1000),
+        };
+        assert_eq!(
+            channel.or(guild),
+            RetentionPolicy {
+                max_age_days: Some(30),
+                max_messages: Some(1000),
+            }
+        );
+    }
+
+    #[test]
+    fn max_age_caps_every_policy() {
+        let policy = |max_age_days| RetentionPolicy {
+            max_age_days,
+            max_messages: Some(100),
+        };
+        assert_eq!(policy(None).capped(180), policy(Some(180)));
+        assert_eq!(policy(Some(30)).capped(180), policy(Some(30)));
+        assert_eq!(policy(Some(365)).capped(180), policy(Some(180)));
+        assert!(!RetentionPolicy::default().capped(180).is_empty());
+    }
+
+    #[tokio::test]
+    async fn effective_retention_defaults_to_max_age() {
+        let pool = memory_pool().await;
+        let policy = effective_retention(
+            &pool,
+            serenity::GuildId::new(1),
+            serenity::ChannelId::new(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            policy.max_age_days,
+            Some(crate::config::get().retention.max_age_days)
+        );
+    }
+
+    #[tokio::test]
+    async fn prune_channel_before_shrinks_ranges_and_fts() {
+        let pool = memory_pool().await;
+        for id in [10, 20, 30, 40] {
+            insert_row(&pool, id, "hello").await;
+        }
+        add_sync_range(&pool, 1, 5, 15).await.unwrap();
+        add_sync_range(&pool, 1, 18, 45).await.unwrap();
+
+        let deleted = prune_channel_before(&pool, serenity::ChannelId::new(1), 25)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 2);
+
+        let ranges: Vec<(i64, i64)> =
+            sqlx::query_as("SELECT start_id, end_id FROM sync_ranges WHERE channel_id = 1")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(ranges, vec![(25, 45)]);
+
+        let fts: Vec<(i64,)> = sqlx::query_as(
+            "SELECT rowid FROM messages_fts WHERE messages_fts MATCH 'hello' ORDER BY rowid",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(fts, vec![(30,), (40,)]);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BotCaching {
+    #[default]
+    Humans,
+    All,
+    Webhooks,
+}
+
+impl BotCaching {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BotCaching::Humans => "humans",
+            BotCaching::All => "all",
+            BotCaching::Webhooks => "webhooks",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "all" => BotCaching::All,
+            "webhooks" => BotCaching::Webhooks,
+            _ => BotCaching::Humans,
+        }
+    }
+}
+
+// 채널에서 어떤 봇, 웹훅 메세지를 캐싱할지
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BotFilter {
+    pub mode: BotCaching,
+    pub allowlist: Vec<u64>,
+}
+
+impl BotFilter {
+    pub fn allows(&self, msg: &serenity::Message) -> bool {
+        if !msg.author.bot {
+            return true;
+        }
+        if self.allowlist.contains(&msg.author.id.get()) {
+            return true;
+        }
+        match self.mode {
+            BotCaching::Humans => false,
+            BotCaching::All => true,
+            // 웹훅 메세지도 author.bot이 true
+            BotCaching::Webhooks => msg.webhook_id.is_some(),
+        }
+    }
+}
+
+pub async fn get_bot_filter(
+    pool: &SqlitePool,
+    channel_id: serenity::ChannelId,
+) -> Result<BotFilter, sqlx::Error> {
+    Ok(load_settings(pool, SettingScope::Channel(channel_id))
+        .await?
+        .bot_filter())
+}
+
+#[cfg(test)]
+mod bot_filter_tests {
+    use super::*;
+
+    fn message(bot: bool, webhook: bool, author_id: u64) -> serenity::Message {
+        let mut msg = serenity::Message::default();
+        msg.author.bot = bot;
+        msg.author.id = serenity::UserId::new(author_id);
+        msg.webhook_id = webhook.then(|| serenity::WebhookId::new(author_id));
+        msg
+    }
+
+    #[test]
+    fn humans_are_always_cached() {
+        let filter = BotFilter::default();
+        assert!(filter.allows(&message(false, false, 1)));
+        assert!(!filter.allows(&message(true, false, 2)));
+        assert!(!filter.allows(&message(true, true, 3)));
+    }
+
+    #[test]
+    fn webhooks_mode_skips_regular_bots() {
+        let filter = BotFilter {
+            mode: BotCaching::Webhooks,
+            allowlist: vec![],
+        };
+        assert!(filter.allows(&message(true, true, 3)));
+        assert!(!filter.allows(&message(true, false, 2)));
+    }
+
+    #[test]
+    fn allowlisted_bot_is_cached() {
+        let filter = BotFilter {
+            mode: BotCaching::Humans,
+            allowlist: vec![2],
+        };
+        assert!(filter.allows(&message(true, false, 2)));
+        assert!(!filter.allows(&message(true, false, 4)));
+    }
+}
+
+#[cfg(test)]
+mod removal_tests {
+    use super::*;
+    use serenity::{ChannelId, GuildId};
+
+    async fn insert_row(pool: &SqlitePool, message_id: i64, channel_id: i64, guild_id: i64) {
+        sqlx::query(
+            "INSERT INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at)
+             VALUES (?, ?, ?, 1, 'user', 'hello', 0)",
+        )
+        .bind(message_id)
+        .bind(channel_id)
+        .bind(guild_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn count(pool: &SqlitePool, sql: &str) -> i64 {
+        sqlx::query_scalar(sql).fetch_one(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn forget_channel_removes_settings() {
+        let pool = memory_pool().await;
+        insert_row(&pool, 1, 10, 100).await;
+        insert_row(&pool, 2, 11, 100).await;
+        add_sync_range(&pool, 10, 1, 1).await.unwrap();
+        save_setting(
+            &pool,
+            GuildId::new(100),
+            SettingScope::Channel(ChannelId::new(10)),
+            Setting::Caching,
+            Some(SettingValue::Bool(true)),
+        )
+        .await
+        .unwrap();
+
+        forget_channel(&pool, ChannelId::new(10)).await.unwrap();
+
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM messages").await, 1);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM sync_ranges").await, 0);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM settings").await, 0);
+    }
+
+    #[tokio::test]
+    async fn forget_guild_keeps_other_guilds() {
+        let pool = memory_pool().await;
+        insert_row(&pool, 1, 10, 100).await;
+        insert_row(&pool, 2, 20, 200).await;
+        add_sync_range(&pool, 10, 1, 1).await.unwrap();
+        save_setting(
+            &pool,
+            GuildId::new(100),
+            SettingScope::Guild(GuildId::new(100)),
+            Setting::Caching,
+            Some(SettingValue::Bool(true)),
+        )
+        .await
+        .unwrap();
+        save_setting(
+            &pool,
+            GuildId::new(300),
+            SettingScope::Guild(GuildId::new(300)),
+            Setting::Caching,
+            Some(SettingValue::Bool(true)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            list_known_guilds(&pool).await.unwrap(),
+            vec![GuildId::new(100), GuildId::new(200), GuildId::new(300)]
+        );
+
+        forget_guild(&pool, GuildId::new(100), &[]).await.unwrap();
+        forget_guild(&pool, GuildId::new(300), &[]).await.unwrap();
+
+        assert_eq!(
+            list_known_guilds(&pool).await.unwrap(),
+            vec![GuildId::new(200)]
+        );
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM sync_ranges").await, 0);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM settings").await, 0);
+    }
+}
+
+#[cfg(test)]
+mod member_tests {
+    use super::*;
+
+    fn message(id: u64, nick: Option<Option<&str>>) -> serenity::Message {
+        let mut msg = serenity::Message::default();
+        msg.id = serenity::MessageId::new(id);
+        msg.channel_id = serenity::ChannelId::new(10);
+        msg.guild_id = Some(serenity::GuildId::new(100));
+        msg.author.id = serenity::UserId::new(1);
+        msg.author.name = "username".to_owned();
+        msg.author.global_name = Some("Global".to_owned());
+        msg.member = nick.map(|nick| {
+            let member = serenity::json::json!({ "nick": nick, "roles": [] });
+            Box::new(serenity::json::from_value(member).unwrap())
+        });
+        msg
+    }
+
+    #[tokio::test]
+    async fn nickname_survives_messages_without_member() {
+        let pool = memory_pool().await;
+
+        insert_messages(
+            &pool,
+            &[message(1, Some(Some("Nick")))],
+            100,
+            &Redactor::default(),
+        )
+        .await
+        .unwrap();
+        // api로 가져온 메세지에는 member가 없음
+        insert_messages(&pool, &[message(2, None)], 100, &Redactor::default())
+            .await
+            .unwrap();
+
+        let result = get_message(&pool, 1).await.unwrap().unwrap();
+        assert_eq!(result.author_display_name(), "Nick");
+
+        // 별명을 지운 뒤 보낸 메세지
+        insert_messages(&pool, &[message(3, Some(None))], 100, &Redactor::default())
+            .await
+            .unwrap();
+        let result = get_message(&pool, 1).await.unwrap().unwrap();
+        assert_eq!(result.author_display_name(), "Global");
+    }
+}
+
+#[cfg(test)]
+mod privacy_tests {
+    use super::*;
+
+    fn message(id: u64, author_id: u64, guild_id: u64) -> serenity::Message {
+        let mut msg = serenity::Message::default();
+        msg.id = serenity::MessageId::new(id);
+        msg.channel_id = serenity::ChannelId::new(guild_id + 1);
+        msg.guild_id = Some(serenity::GuildId::new(guild_id));
+        msg.author.id = serenity::UserId::new(author_id);
+        msg.author.name = format!("user{author_id}");
+        msg
+    }
+
+    async fn count(pool: &SqlitePool, sql: &str) -> i64 {
+        sqlx::query_scalar(sql).fetch_one(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn forget_user_removes_rows_and_blocks_new_ones() {
+        let pool = memory_pool().await;
+        insert_messages(
+            &pool,
+            &[message(1, 7, 100), message(2, 8, 100)],
+            100,
+            &Redactor::default(),
+        )
+        .await
+        .unwrap();
+        insert_messages(&pool, &[message(3, 7, 200)], 200, &Redactor::default())
+            .await
+            .unwrap();
+
+        assert_eq!(forget_user(&pool, serenity::UserId::new(7)).await.unwrap(), 2);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM messages").await, 1);
+        assert_eq!(
+            count(&pool, "SELECT COUNT(*) FROM members WHERE user_id = 7").await,
+            0
+        );
+        assert_eq!(
+            list_opted_out_users(&pool, &[7, 8]).await.unwrap(),
+            HashSet::from([7])
+        );
+
+        // 실시간 캐싱, api 검색, 아카이브 가져오기 모두 건너뜀
+        insert_messages(&pool, &[message(4, 7, 100)], 100, &Redactor::default())
+            .await
+            .unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let inserted = insert_archived_messages_in(
+            &mut conn,
+            100,
+            101,
+            &[ArchivedMessage::from_message(&message(5, 7, 100))],
+            &Redactor::default(),
+        )
+        .await
+        .unwrap();
+        drop(conn);
+
+        assert_eq!(inserted, 0);
+        assert_eq!(
+            count(&pool, "SELECT COUNT(*) FROM messages WHERE author_id = 7").await,
+            0
+        );
+        assert_eq!(
+            count(&pool, "SELECT COUNT(*) FROM members WHERE user_id = 7").await,
+            0
+        );
+    }
+}
+
+#[cfg(test)]
+mod redaction_tests {
+    use super::*;
+
+    fn message(id: u64, content: &str) -> serenity::Message {
+        let mut msg = serenity::Message::default();
+        msg.id = serenity::MessageId::new(id);
+        msg.channel_id = serenity::ChannelId::new(101);
+        msg.guild_id = Some(serenity::GuildId::new(100));
+        msg.author.id = serenity::UserId::new(7);
+        msg.content = content.to_owned();
+        msg
+    }
+
+    async fn enable_emails(pool: &SqlitePool) {
+        let guild_id = serenity::GuildId::new(100);
+        save_setting(
+            pool,
+            guild_id,
+            SettingScope::Guild(guild_id),
+            Setting::Redaction,
+            Some(SettingValue::Text("emails".to_owned())),
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn stored(pool: &SqlitePool, sql: &str) -> Vec<String> {
+        sqlx::query_scalar(sql).fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn writes_store_redacted_content() {
+        let pool = memory_pool().await;
+        enable_emails(&pool).await;
+
+        let redactor = load_redactor(&pool, 100).await.unwrap();
+        insert_messages(
+            &pool,
+            &[message(1, "메일 a@example.com 으로")],
+            100,
+            &redactor,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            stored(&pool, "SELECT content FROM messages").await,
+            vec!["메일 [가려진 이메일] 으로"]
+        );
+        assert_eq!(
+            stored(&pool, "SELECT content FROM messages_fts").await,
+            vec!["메일 [가려진 이메일] 으로"]
+        );
+
+        let event: serenity::MessageUpdateEvent = serenity::json::from_value(serenity::json::json!({
+            "id": "1",
+            "channel_id": "101",
+            "guild_id": "100",
+            "content": "아니 b@example.com 이요",
+        }))
+        .unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        update_message_in(&mut conn, &event, true, &redactor)
+            .await
+            .unwrap();
+        drop(conn);
+
+        assert_eq!(
+            stored(&pool, "SELECT content FROM messages_fts").await,
+            vec!["아니 [가려진 이메일] 이요"]
+        );
+        assert_eq!(
+            stored(&pool, "SELECT content FROM message_revisions").await,
+            vec!["메일 [가려진 이메일] 으로"]
+        );
+    }
+
+    #[tokio::test]
+    async fn existing_messages_are_redacted() {
+        let pool = memory_pool().await;
+        insert_messages(
+            &pool,
+            &[message(1, "a@example.com"), message(2, "그냥 메세지")],
+            100,
+            &Redactor::default(),
+        )
+        .await
+        .unwrap();
+        enable_emails(&pool).await;
+
+        let redactor = load_redactor(&pool, 100).await.unwrap();
+        let changed = redact_guild_messages(&pool, serenity::GuildId::new(100), &redactor)
+            .await
+            .unwrap();
+        assert_eq!(changed, 1);
+        assert_eq!(
+            stored(&pool, "SELECT content FROM messages_fts ORDER BY rowid").await,
+            vec!["[가려진 이메일]", "그냥 메세지"]
+        );
+    }
+}
+
+#[cfg(test)]
+mod user_data_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn collects_only_the_callers_rows() {
+        let pool = memory_pool().await;
+        for (message_id, author_id) in [(1, 7), (2, 8), (3, 7)] {
+            let mut msg = serenity::Message::default();
+            msg.id = serenity::MessageId::new(message_id);
+            msg.channel_id = serenity::ChannelId::new(10);
+            msg.author.id = serenity::UserId::new(author_id);
+            msg.author.name = format!("user{author_id}");
+            msg.content = format!("message {message_id}");
+            insert_messages(&pool, &[msg], 100, &Redactor::default())
+                .await
+                .unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO message_revisions (message_id, channel_id, guild_id, content, edited_at)
+             VALUES (3, 10, 100, 'before', 5), (2, 10, 100, 'other', 5)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        set_version_subscription(&pool, 7, true).await.unwrap();
+
+        let data = collect_user_data(&pool, serenity::UserId::new(7))
+            .await
+            .unwrap();
+
+        assert_eq!(data.user_id, 7);
+        assert_eq!(data.opted_out_at, None);
+        assert!(data.version_subscription.unwrap().enabled);
+        assert_eq!(data.members.len(), 1);
+        let ids: Vec<_> = data.messages.iter().map(|m| m.message_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert!(data.messages[0].revisions.is_empty());
+        assert_eq!(data.messages[1].revisions[0].content, "before");
+    }
+}
+
+#[cfg(test)]
+mod range_tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn range() -> impl Strategy<Value = Range> {
+        (0i64..200, 0i64..200).prop_map(|(a, b)| Range::new(a, b))
+    }
+
+    fn covers(ranges: &[Range], id: i64) -> bool {
+        ranges.iter().any(|r| r.start <= id && id <= r.end)
+    }
+
+    // 정렬되어 있고, 겹치거나 맞닿은 range가 없음
+    fn is_normalized(ranges: &[Range]) -> bool {
+        ranges.windows(2).all(|w| w[0].end + 1 < w[1].start)
+    }
+
+    proptest! {
+        #[test]
+        fn touches_is_symmetric(a in range(), b in range()) {
+            prop_assert_eq!(a.touches(&b), b.touches(&a));
+        }
+
+        #[test]
+        fn touches_means_no_gap(a in range(), b in range()) {
+            // 사이에 빠진 id가 없으면 맞닿은 것
+            let gap = (min(a.end, b.end) + 1..max(a.start, b.start)).count();
+            prop_assert_eq!(a.touches(&b), gap == 0);
+        }
+
+        #[test]
+        fn merge_covers_exactly_both(a in range(), b in range()) {
+            match a.merge(&b) {
+                Some(merged) => {
+                    prop_assert!(merged.contains(&a) && merged.contains(&b));
+                    for id in merged.start..=merged.end {
+                        prop_assert!(covers(&[a, b], id));
+                    }
+                }
+                None => prop_assert!(!a.touches(&b)),
+            }
+        }
+
+        #[test]
+        fn clamp_start_keeps_only_newer_ids(a in range(), min_start in 0i64..250) {
+            match a.clamp_start(min_start) {
+                Some(clamped) => {
+                    prop_assert!(a.contains(&clamped));
+                    prop_assert_eq!(clamped.start, a.start.max(min_start));
+                    prop_assert_eq!(clamped.end, a.end);
+                }
+                None => prop_assert!(a.end < min_start),
+            }
+        }
+
+        #[test]
+        fn merge_ranges_is_normalized(inputs in prop::collection::vec(range(), 0..20)) {
+            let merged = inputs
+                .iter()
+                .fold(Vec::new(), |merged, r| merge_ranges(merged, *r));
+
+            prop_assert!(is_normalized(&merged));
+            for id in 0..200 {
+                prop_assert_eq!(covers(&merged, id), covers(&inputs, id));
+            }
+        }
+
+        #[test]
+        fn sync_gaps_fill_between_ranges(inputs in prop::collection::vec(range(), 1..20)) {
+            let merged = inputs
+                .iter()
+                .fold(Vec::new(), |merged, r| merge_ranges(merged, *r));
+            let gaps = sync_gaps(&merged);
+
+            prop_assert_eq!(gaps.len(), merged.len() - 1);
+            let (first, last) = (merged[0].start, merged[merged.len() - 1].end);
+            for id in first..=last {
+                prop_assert_ne!(covers(&gaps, id), covers(&merged, id));
+            }
+        }
+
+        #[test]
+        fn incremental_merge_matches_merge_ranges(inputs in prop::collection::vec(range(), 1..10)) {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let stored = rt.block_on(async {
+                let pool = memory_pool().await;
+                for r in &inputs {
+                    let merged = add_sync_range(&pool, 1, r.start, r.end).await.unwrap();
+                    assert!(merged.contains(r));
+                }
+                sqlx::query("SELECT start_id, end_id FROM sync_ranges ORDER BY start_id")
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|row| Range::new(row.get("start_id"), row.get("end_id")))
+                    .collect::<Vec<_>>()
+            });
+
+            let expected = inputs
+                .iter()
+                .fold(Vec::new(), |merged, r| merge_ranges(merged, *r));
+            prop_assert_eq!(stored, expected);
+        }
+    }
+}
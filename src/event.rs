@@ -1,6 +1,10 @@
-use crate::{database, Data, Error};
-use poise::serenity_prelude::{Context, FullEvent};
+use crate::storage::Storage;
+use crate::{caching, command, database, ingest, reconcile, shard, Data, Error};
+use poise::serenity_prelude::{
+    ChannelId, ChannelType, Context, FullEvent, GuildId, Interaction, ShardInfo,
+};
 use poise::FrameworkContext;
+use std::collections::HashSet;
 
 pub async fn event_handler(
     ctx: &Context,
@@ -10,7 +14,65 @@ pub async fn event_handler(
 ) -> Result<(), Error> {
     register_command(ctx, event, &framework).await?;
 
-    if let Err(e) = handle_cache_event(data, event).await {
+    // Ready는 shard마다 오고, guilds에는 그 shard가 맡은 서버만 있음
+    if let FullEvent::Ready { data_about_bot } = event {
+        let shard = data_about_bot.shard;
+        let guilds: HashSet<GuildId> = data_about_bot.guilds.iter().map(|g| g.id).collect();
+        match shard {
+            Some(shard) => tracing::info!(
+                "Shard {}/{} ready with {} guilds",
+                shard.id.0,
+                shard.total,
+                guilds.len()
+            ),
+            None => tracing::info!("Ready with {} guilds", guilds.len()),
+        }
+        forget_live_ranges(ctx, data, &guilds);
+
+        let storage = data.storage.clone();
+        let http = ctx.http.clone();
+        let live_ranges = data.live_ranges.clone();
+        let settings = data.settings.clone();
+        tokio::spawn(async move {
+            // 나간 서버의 채널까지 reconcile 하지 않도록 먼저 정리
+            sweep_departed_guilds(storage.as_ref(), shard, &guilds).await;
+            settings.invalidate_all();
+            reconcile::run(storage, settings, http, live_ranges, &guilds).await;
+        });
+    }
+
+    if let FullEvent::ShardStageUpdate { event } = event {
+        tracing::info!(
+            "Shard {} {} -> {}",
+            event.shard_id.0,
+            event.old,
+            event.new
+        );
+    }
+
+    if let FullEvent::InteractionCreate {
+        interaction: Interaction::Component(component),
+    } = event
+    {
+        command::handle_component(ctx, data, component).await?;
+    }
+
+    // 검색 결과에 보여줄 별명, 프로필 사진 갱신. GUILD_MEMBERS intent가 있어야 받음
+    if let FullEvent::GuildMemberUpdate { event, .. } = event
+        && let Err(e) = data.storage.update_member(event).await
+    {
+        tracing::error!("Member update error: {e:?}");
+    }
+
+    if let Err(e) = handle_channel_create(ctx, data, event).await {
+        tracing::error!("Backfill enable error: {e:?}");
+    }
+
+    if let Err(e) = handle_removal_event(ctx, data, event).await {
+        tracing::error!("Purge error: {e:?}");
+    }
+
+    if let Err(e) = handle_cache_event(ctx, data, event).await {
         tracing::error!("Cache error: {e:?}");
     }
 
@@ -24,7 +86,7 @@ async fn register_command(
 ) -> Result<(), Error> {
     if let FullEvent::GuildCreate { guild, is_new } = event {
         let is_new = is_new.unwrap_or(false);
-        if is_new || cfg!(debug_assertions) {
+        if is_new || crate::config::get().discord.dev_mode {
             tracing::info!(
                 "Registering commands in guild: {} (ID: {}) (new: {})",
                 guild.name, guild.id, is_new
@@ -37,18 +99,157 @@ async fn register_command(
     Ok(())
 }
 
-async fn handle_cache_event(data: &Data, event: &FullEvent) -> Result<(), Error> {
+// 캐싱이 켜진 서버나 category에 새로 생긴 채널과 thread는 /config를 거치지 않으므로
+// 여기서 백필 상태를 만듦. 백필은 SQLite 저장소에서만 됨
+async fn handle_channel_create(ctx: &Context, data: &Data, event: &FullEvent) -> Result<(), Error> {
+    let channel = match event {
+        FullEvent::ChannelCreate { channel } => channel,
+        FullEvent::ThreadCreate { thread } => thread,
+        _ => return Ok(()),
+    };
+    if !matches!(
+        channel.kind,
+        ChannelType::Text
+            | ChannelType::News
+            | ChannelType::PublicThread
+            | ChannelType::PrivateThread
+            | ChannelType::NewsThread
+    ) {
+        return Ok(());
+    }
+    let Some(pool) = data.storage.sqlite() else {
+        return Ok(());
+    };
+    if caching::is_caching_enabled(ctx, &data.settings, Some(channel.guild_id), channel.id).await? {
+        database::enable_backfill(pool, channel.guild_id, channel.id).await?;
+    }
+    Ok(())
+}
+
+// 삭제된 채널, thread와 나간 서버의 데이터는 다시 쓸 일이 없으므로 설정까지 삭제
+async fn handle_removal_event(ctx: &Context, data: &Data, event: &FullEvent) -> Result<(), Error> {
+    match event {
+        FullEvent::ChannelDelete { channel, .. } => {
+            // 채널과 같이 사라지는 thread는 따로 이벤트가 오지 않음
+            let threads: Vec<ChannelId> = ctx
+                .cache
+                .guild(channel.guild_id)
+                .map(|guild| {
+                    guild
+                        .threads
+                        .iter()
+                        .filter(|t| t.parent_id == Some(channel.id))
+                        .map(|t| t.id)
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            for channel_id in threads.into_iter().chain([channel.id]) {
+                data.storage.forget_channel(channel_id).await?;
+                data.live_ranges.remove(&channel_id);
+            }
+        }
+        FullEvent::ThreadDelete { thread, .. } => {
+            data.storage.forget_channel(thread.id).await?;
+            data.live_ranges.remove(&thread.id);
+        }
+        FullEvent::GuildDelete { incomplete, full } => {
+            // 장애로 잠깐 사라진 서버는 다시 돌아옴
+            if incomplete.unavailable {
+                return Ok(());
+            }
+            let channels: Vec<ChannelId> = full
+                .as_ref()
+                .map(|guild| {
+                    guild
+                        .channels
+                        .keys()
+                        .copied()
+                        .chain(guild.threads.iter().map(|t| t.id))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            tracing::info!("Removed from guild {}, purging data", incomplete.id);
+            data.storage.forget_guild(incomplete.id, &channels).await?;
+            for channel_id in channels {
+                data.live_ranges.remove(&channel_id);
+            }
+        }
+        _ => return Ok(()),
+    }
+    data.settings.invalidate_all();
+    Ok(())
+}
+
+// 새 세션으로 다시 연결된 shard는 끊긴 동안의 이벤트를 놓쳤으므로 live range를 이어 쓰면 안됨
+// 처음 연결될 때는 cache에 서버가 없고 지울 live range도 없음
+fn forget_live_ranges(ctx: &Context, data: &Data, guilds: &HashSet<GuildId>) {
+    for guild_id in guilds {
+        let channels: Vec<ChannelId> = ctx
+            .cache
+            .guild(*guild_id)
+            .map(|guild| {
+                guild
+                    .channels
+                    .keys()
+                    .copied()
+                    .chain(guild.threads.iter().map(|t| t.id))
+                    .collect()
+            })
+            .unwrap_or_default();
+        for channel_id in channels {
+            data.live_ranges.remove(&channel_id);
+        }
+    }
+}
+
+// 봇이 꺼져있던 동안 추방된 서버의 데이터 정리
+// 다른 shard가 맡은 서버는 이 shard의 Ready에 없으므로 건드리지 않음
+async fn sweep_departed_guilds(
+    storage: &dyn Storage,
+    shard: Option<ShardInfo>,
+    current: &HashSet<GuildId>,
+) {
+    let known = match storage.list_known_guilds().await {
+        Ok(known) => known,
+        Err(e) => {
+            tracing::warn!("list_known_guilds failed: {}", e);
+            return;
+        }
+    };
+
+    let departed = known.into_iter().filter(|id| {
+        shard.is_none_or(|shard| shard::owns(shard, *id)) && !current.contains(id)
+    });
+    for guild_id in departed {
+        tracing::info!("Bot is no longer in guild {}, purging data", guild_id);
+        if let Err(e) = storage.forget_guild(guild_id, &[]).await {
+            tracing::warn!("forget_guild failed for {}: {}", guild_id, e);
+        }
+    }
+}
+
+async fn handle_cache_event(ctx: &Context, data: &Data, event: &FullEvent) -> Result<(), Error> {
     // 1. 캐싱 대상 이벤트인지 확인하고 Channel ID 추출
-    let channel_id = match event {
-        FullEvent::Message { new_message } => new_message.channel_id,
-        FullEvent::MessageUpdate { event, .. } => event.channel_id,
-        FullEvent::MessageDelete { channel_id, .. } => *channel_id,
-        FullEvent::MessageDeleteBulk { channel_id, .. } => *channel_id,
+    let (guild_id, channel_id) = match event {
+        FullEvent::Message { new_message } => (new_message.guild_id, new_message.channel_id),
+        FullEvent::MessageUpdate { event, .. } => (event.guild_id, event.channel_id),
+        FullEvent::MessageDelete {
+            guild_id,
+            channel_id,
+            ..
+        } => (*guild_id, *channel_id),
+        FullEvent::MessageDeleteBulk {
+            guild_id,
+            channel_id,
+            ..
+        } => (*guild_id, *channel_id),
         _ => return Ok(()), // 캐싱과 무관한 이벤트는 무시
     };
 
-    // 2. 캐싱 활성화 여부 확인
-    if !database::is_channel_caching_enabled(&data.database, channel_id)
+    // 2. 캐싱 활성화 여부 확인 (채널 -> category -> 서버 설정 순)
+    if !caching::is_caching_enabled(ctx, &data.settings, guild_id, channel_id)
         .await
         .unwrap_or(false)
     {
@@ -56,53 +257,67 @@ async fn handle_cache_event(data: &Data, event: &FullEvent) -> Result<(), Error>
     }
 
     // 3. DB 작업 수행
-    // 봇이 꺼져있던 시간 중에 발생한 것 중 새 메세지는 검색 로직에서 lazy하게 처리되나
-    // 그 외 것들은 처리 못함
+    // 봇이 꺼져있던 시간 중에 발생한 것은 Ready 때 reconcile에서 처리
     match event {
         FullEvent::Message { new_message } => {
-            if !new_message.author.bot {
-                database::insert_message(&data.database, new_message).await?;
-
-                // Update Range Logic (Session Continuity)
-                let msg_id = new_message.id.get() as i64;
-                let channel_id = new_message.channel_id;
-
-                let mut range_to_update = database::Range::new(msg_id, msg_id);
-
-                // Check in-memory session range
-                if let Some(mut session_range) = data.live_ranges.get_mut(&channel_id) {
-                    // live는 range 무조건 확장
-                    let new_merged = database::Range::new(session_range.start, msg_id);
-                    *session_range = new_merged;
-                    range_to_update = new_merged;
-                } else {
-                    // New session for this channel
-                    data.live_ranges.insert(channel_id, range_to_update);
-                }
-
-                // Sync to DB
-                database::add_sync_range(
-                    &data.database,
-                    channel_id.get() as i64,
-                    range_to_update.start,
-                    range_to_update.end,
-                )
+            // 봇, 웹훅 메세지는 채널 설정에 따라 writer에서 걸러짐
+            data.ingest
+                .send(ingest::Write::Insert(Box::new(new_message.clone())))
                 .await?;
+
+            // Update Range Logic (Session Continuity)
+            let msg_id = new_message.id.get() as i64;
+            let channel_id = new_message.channel_id;
+
+            let mut range_to_update = database::Range::new(msg_id, msg_id);
+
+            // Check in-memory session range
+            if let Some(mut session_range) = data.live_ranges.get_mut(&channel_id) {
+                // live는 range 무조건 확장
+                let new_merged = database::Range::new(session_range.start, msg_id);
+                *session_range = new_merged;
+                range_to_update = new_merged;
+            } else {
+                // New session for this channel
+                data.live_ranges.insert(channel_id, range_to_update);
             }
+
+            // Sync to DB. 메세지와 같은 batch에서 반영됨
+            data.ingest
+                .send(ingest::Write::Range {
+                    channel_id,
+                    range: range_to_update,
+                })
+                .await?;
         }
         FullEvent::MessageUpdate { event, .. } => {
-            database::update_message(&data.database, event).await?;
+            let keep_revision = match event.guild_id {
+                Some(guild_id) => {
+                    data.settings.is_revisions_enabled(guild_id).await?
+                }
+                None => false,
+            };
+            data.ingest
+                .send(ingest::Write::Update {
+                    event: Box::new(event.clone()),
+                    keep_revision,
+                })
+                .await?;
         }
         FullEvent::MessageDelete {
             deleted_message_id, ..
         } => {
-            database::delete_message(&data.database, *deleted_message_id).await?;
+            data.ingest
+                .send(ingest::Write::Delete(vec![*deleted_message_id]))
+                .await?;
         }
         FullEvent::MessageDeleteBulk {
             multiple_deleted_messages_ids,
             ..
         } => {
-            database::delete_messages(&data.database, multiple_deleted_messages_ids).await?;
+            data.ingest
+                .send(ingest::Write::Delete(multiple_deleted_messages_ids.clone()))
+                .await?;
         }
         _ => {}
     }
@@ -0,0 +1,446 @@
+use crate::{Error, database};
+use poise::serenity_prelude::{ChannelId, GuildId, Message, MessageId, UserId};
+use serde::{Deserialize, Deserializer};
+use sqlx::SqlitePool;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+const BATCH_SIZE: usize = 1_000; // transaction 하나에 넣을 메세지 수
+
+// 채널 하나를 가져온 결과
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportedChannel {
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub messages: usize, // 덤프에 있던 메세지
+    pub inserted: u64,   // 새로 캐시된 메세지
+    // 덤프가 빠짐없이 담고 있는 구간. 본인 메세지만 있는 data package는 None
+    pub range: Option<database::Range>,
+}
+
+// DiscordChatExporter JSON 파일, 그런 파일이 든 폴더, 또는 discord data package 폴더
+pub async fn import_path(pool: &SqlitePool, path: &Path) -> Result<Vec<ImportedChannel>, Error> {
+    if path.is_file() {
+        return Ok(vec![import_dce_file(pool, path).await?]);
+    }
+    if path.join("messages").is_dir() {
+        return import_data_package(pool, path).await;
+    }
+
+    let mut files: Vec<_> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    files.retain(|p| p.extension().is_some_and(|ext| ext == "json"));
+    files.sort();
+
+    let mut imported = Vec::new();
+    for file in files {
+        imported.push(import_dce_file(pool, &file).await?);
+    }
+    Ok(imported)
+}
+
+// discord는 id를 문자열로, data package의 일부 파일은 숫자로 줌
+fn snowflake<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Text(String),
+        Number(u64),
+    }
+    match Id::deserialize(deserializer)? {
+        Id::Text(id) => id.parse().map_err(serde::de::Error::custom),
+        Id::Number(id) => Ok(id),
+    }
+}
+
+fn optional_snowflake<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(deserialize_with = "snowflake")] u64);
+    Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(id)| id))
+}
+
+#[derive(Deserialize)]
+struct DceExport {
+    guild: DceId,
+    channel: DceId,
+    messages: Vec<DceMessage>,
+}
+
+#[derive(Deserialize)]
+struct DceId {
+    #[serde(deserialize_with = "snowflake")]
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct DceMessage {
+    #[serde(deserialize_with = "snowflake")]
+    id: u64,
+    #[serde(default)]
+    content: String,
+    author: DceAuthor,
+    reference: Option<DceReference>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DceAuthor {
+    #[serde(deserialize_with = "snowflake")]
+    id: u64,
+    name: String,
+    nickname: Option<String>,
+    #[serde(default)]
+    is_bot: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DceReference {
+    #[serde(default, deserialize_with = "optional_snowflake")]
+    message_id: Option<u64>,
+    #[serde(default, deserialize_with = "optional_snowflake")]
+    channel_id: Option<u64>,
+}
+
+async fn import_dce_file(pool: &SqlitePool, path: &Path) -> Result<ImportedChannel, Error> {
+    let export: DceExport = serde_json::from_reader(BufReader::new(File::open(path)?))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    // DM 내보내기는 guild id가 0
+    if export.guild.id == 0 {
+        return Err(format!("{}: not a server channel", path.display()).into());
+    }
+    let guild_id = GuildId::new(export.guild.id);
+    let channel_id = ChannelId::new(export.channel.id);
+
+    let messages: Vec<Message> = export
+        .messages
+        .into_iter()
+        .map(|m| {
+            let mut msg = message(guild_id, channel_id, m.id, m.author.id, m.content);
+            // DiscordChatExporter의 nickname은 서버 별명이 없으면 표시 이름
+            msg.author.global_name = m.author.nickname.filter(|n| *n != m.author.name);
+            msg.author.name = m.author.name;
+            msg.author.bot = m.author.is_bot;
+            msg.message_reference = m.reference.and_then(|r| {
+                let reply_channel = r.channel_id.map_or(channel_id, ChannelId::new);
+                Some((reply_channel, MessageId::new(r.message_id?)).into())
+            });
+            msg
+        })
+        .collect();
+
+    // 내보낸 메세지 사이에는 빠진 메세지가 없음. 보관 기간이 지난 구간은 빼고 기록
+    let cutoff_id = age_cutoff_id(pool, guild_id, channel_id).await?;
+    let range = span(&messages).and_then(|range| range.clamp_start(cutoff_id));
+    let mut imported = insert(pool, guild_id, channel_id, messages, cutoff_id).await?;
+    if let Some(range) = range {
+        database::add_sync_range(pool, channel_id.get() as i64, range.start, range.end).await?;
+    }
+    imported.range = range;
+    Ok(imported)
+}
+
+#[derive(Deserialize)]
+struct PackageUser {
+    #[serde(deserialize_with = "snowflake")]
+    id: u64,
+    username: String,
+    global_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PackageChannel {
+    guild: Option<DceId>,
+    #[serde(deserialize_with = "snowflake")]
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct PackageMessage {
+    #[serde(rename = "ID", deserialize_with = "snowflake")]
+    id: u64,
+    #[serde(rename = "Contents", default)]
+    contents: String,
+}
+
+// data package에는 본인이 보낸 메세지만 있으므로 sync range는 기록하지 않음
+async fn import_data_package(pool: &SqlitePool, dir: &Path) -> Result<Vec<ImportedChannel>, Error> {
+    let user: PackageUser =
+        serde_json::from_reader(BufReader::new(File::open(dir.join("account/user.json"))?))?;
+
+    let mut channel_dirs: Vec<_> = std::fs::read_dir(dir.join("messages"))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    channel_dirs.retain(|p| p.join("channel.json").is_file());
+    channel_dirs.sort();
+
+    let mut imported = Vec::new();
+    for channel_dir in channel_dirs {
+        let channel: PackageChannel = serde_json::from_reader(BufReader::new(File::open(
+            channel_dir.join("channel.json"),
+        )?))?;
+        let Some(guild) = channel.guild else {
+            continue; // DM, 그룹 DM
+        };
+        let messages_path = channel_dir.join("messages.json");
+        if !messages_path.is_file() {
+            tracing::warn!("{} has no messages.json, skipped", channel_dir.display());
+            continue;
+        }
+        let package_messages: Vec<PackageMessage> =
+            serde_json::from_reader(BufReader::new(File::open(messages_path)?))?;
+
+        let guild_id = GuildId::new(guild.id);
+        let channel_id = ChannelId::new(channel.id);
+        let messages: Vec<Message> = package_messages
+            .into_iter()
+            .map(|m| {
+                let mut msg = message(guild_id, channel_id, m.id, user.id, m.contents);
+                msg.author.name = user.username.clone();
+                msg.author.global_name = user.global_name.clone();
+                msg
+            })
+            .collect();
+        let cutoff_id = age_cutoff_id(pool, guild_id, channel_id).await?;
+        imported.push(insert(pool, guild_id, channel_id, messages, cutoff_id).await?);
+    }
+    Ok(imported)
+}
+
+fn message(
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    message_id: u64,
+    author_id: u64,
+    content: String,
+) -> Message {
+    let mut msg = Message::default();
+    msg.id = MessageId::new(message_id);
+    msg.channel_id = channel_id;
+    msg.guild_id = Some(guild_id);
+    msg.author.id = UserId::new(author_id);
+    msg.content = content;
+    msg.timestamp = msg.id.created_at();
+    msg
+}
+
+fn span(messages: &[Message]) -> Option<database::Range> {
+    let min = messages.iter().map(|m| m.id.get() as i64).min()?;
+    let max = messages.iter().map(|m| m.id.get() as i64).max()?;
+    Some(database::Range::new(min, max))
+}
+
+// 이 id보다 오래된 메세지는 보관 기간이 지나서 가져와도 정리 작업이 다시 지움
+async fn age_cutoff_id(
+    pool: &SqlitePool,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<i64, Error> {
+    let policy = database::effective_retention(pool, guild_id, channel_id).await?;
+    Ok(policy
+        .age_cutoff_id(chrono::Utc::now().timestamp())
+        .unwrap_or(0))
+}
+
+async fn insert(
+    pool: &SqlitePool,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    mut messages: Vec<Message>,
+    cutoff_id: i64,
+) -> Result<ImportedChannel, Error> {
+    let total = messages.len();
+    messages.retain(|m| m.id.get() as i64 >= cutoff_id);
+    let guild = guild_id.get() as i64;
+    let redactor = database::load_redactor(pool, guild).await?;
+    let mut inserted = 0;
+    for batch in messages.chunks(BATCH_SIZE) {
+        inserted += database::import_messages(pool, batch, guild, &redactor).await?;
+    }
+    Ok(ImportedChannel {
+        guild_id,
+        channel_id,
+        messages: total,
+        inserted,
+        range: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DCE: &str = r#"{
+        "guild": { "id": "100", "name": "서버" },
+        "channel": { "id": "10", "type": "GuildTextChat", "name": "잡담" },
+        "dateRange": { "after": null, "before": null },
+        "messages": [
+            {
+                "id": "1000", "type": "Default", "timestamp": "2020-01-01T00:00:00+00:00",
+                "content": "안녕하세요",
+                "author": { "id": "1", "name": "user", "nickname": "유저", "isBot": false }
+            },
+            {
+                "id": "1001", "type": "Default", "timestamp": "2020-01-01T00:00:01+00:00",
+                "content": "bot says hi",
+                "author": { "id": "2", "name": "bot", "nickname": "bot", "isBot": true }
+            },
+            {
+                "id": "1002", "type": "Reply", "timestamp": "2020-01-01T00:00:02+00:00",
+                "content": "반가워요",
+                "author": { "id": "3", "name": "other", "nickname": "other", "isBot": false },
+                "reference": { "messageId": "1000", "channelId": "10", "guildId": "100" }
+            }
+        ],
+        "messageCount": 3
+    }"#;
+
+    // 보관 기간 안에 들어가도록 덤프의 메세지 id를 최근 id로 바꿈
+    fn recent_ids(json: &str, base: i64, ids: &[i64]) -> String {
+        ids.iter().fold(json.to_owned(), |json, id| {
+            json.replace(&format!("\"{id}\""), &format!("\"{}\"", base + id))
+                .replace(
+                    &format!("\"ID\": {id},"),
+                    &format!("\"ID\": {},", base + id),
+                )
+        })
+    }
+
+    fn now_id() -> i64 {
+        database::snowflake_from_timestamp(chrono::Utc::now().timestamp())
+    }
+
+    async fn rows(pool: &SqlitePool) -> Vec<(i64, String, Option<i64>)> {
+        sqlx::query_as("SELECT message_id, content, reply_to_id FROM messages ORDER BY message_id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn dce_export_is_imported_once() {
+        let base = now_id();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.json");
+        std::fs::write(&path, recent_ids(DCE, base, &[1000, 1001, 1002])).unwrap();
+
+        let pool = database::memory_pool().await;
+        // 이미 캐시된 메세지는 더 최신일 수 있으니 그대로 둠
+        sqlx::query(
+            "INSERT INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at)
+             VALUES (?, 10, 100, 1, 'user', '수정됨', 0)",
+        )
+        .bind(base + 1000)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let imported = import_path(&pool, &path).await.unwrap();
+        assert_eq!(
+            imported,
+            vec![ImportedChannel {
+                guild_id: GuildId::new(100),
+                channel_id: ChannelId::new(10),
+                messages: 3,
+                inserted: 1, // 봇 메세지는 기본 설정에서 제외
+                range: Some(database::Range::new(base + 1000, base + 1002)),
+            }]
+        );
+        assert_eq!(
+            rows(&pool).await,
+            vec![
+                (base + 1000, "수정됨".to_owned(), None),
+                (base + 1002, "반가워요".to_owned(), Some(base + 1000)),
+            ]
+        );
+        assert_eq!(
+            database::list_sync_ranges(&pool, 10).await.unwrap(),
+            vec![database::Range::new(base + 1000, base + 1002)]
+        );
+
+        // 같은 파일을 다시 가져와도 중복되지 않음
+        let again = import_path(&pool, dir.path()).await.unwrap();
+        assert_eq!(again[0].inserted, 0);
+        assert_eq!(rows(&pool).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn dce_export_skips_expired_messages() {
+        let base = now_id();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.json");
+        // 1000만 보관 기간이 지난 메세지
+        std::fs::write(&path, recent_ids(DCE, base, &[1001, 1002])).unwrap();
+
+        let pool = database::memory_pool().await;
+        let imported = import_path(&pool, &path).await.unwrap();
+
+        assert_eq!(imported[0].messages, 3);
+        assert_eq!(imported[0].inserted, 1);
+        assert_eq!(
+            rows(&pool).await,
+            vec![(base + 1002, "반가워요".to_owned(), Some(1000))]
+        );
+        // 보관 기간 경계부터 기록
+        let range = imported[0].range.unwrap();
+        assert!(range.start > 1000 && range.start <= base);
+        assert_eq!(range.end, base + 1002);
+        assert_eq!(
+            database::list_sync_ranges(&pool, 10).await.unwrap(),
+            vec![range]
+        );
+    }
+
+    #[tokio::test]
+    async fn data_package_skips_dms_and_ranges() {
+        let base = now_id();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("account")).unwrap();
+        std::fs::write(
+            root.join("account/user.json"),
+            r#"{ "id": "7", "username": "me", "global_name": "나" }"#,
+        )
+        .unwrap();
+        for (name, channel, messages) in [
+            (
+                "c10",
+                r#"{ "id": "10", "type": 0, "guild": { "id": "100", "name": "서버" } }"#,
+                r#"[{ "ID": 2000, "Timestamp": "2021-01-01 00:00:00", "Contents": "내 메세지", "Attachments": "" }]"#,
+            ),
+            (
+                "c20",
+                r#"{ "id": "20", "type": 1, "recipients": ["7", "8"] }"#,
+                r#"[{ "ID": 3000, "Timestamp": "2021-01-01 00:00:00", "Contents": "DM", "Attachments": "" }]"#,
+            ),
+        ] {
+            let channel_dir = root.join("messages").join(name);
+            std::fs::create_dir_all(&channel_dir).unwrap();
+            std::fs::write(channel_dir.join("channel.json"), channel).unwrap();
+            std::fs::write(
+                channel_dir.join("messages.json"),
+                recent_ids(messages, base, &[2000, 3000]),
+            )
+            .unwrap();
+        }
+
+        let pool = database::memory_pool().await;
+        let imported = import_path(&pool, root).await.unwrap();
+
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].inserted, 1);
+        assert_eq!(imported[0].range, None);
+        assert_eq!(
+            rows(&pool).await,
+            vec![(base + 2000, "내 메세지".to_owned(), None)]
+        );
+        assert!(
+            database::list_sync_ranges(&pool, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
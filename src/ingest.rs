@@ -0,0 +1,495 @@
+use crate::Error;
+use crate::database::{self, BotFilter};
+use crate::redaction::Redactor;
+use crate::settings::Settings;
+use crate::storage::Storage;
+use dashmap::DashMap;
+use poise::serenity_prelude::{ChannelId, Message, MessageId, MessageUpdateEvent};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+const MAX_BATCH: usize = 1_000; // transaction 하나에 넣을 최대 쓰기 수
+const RETRIES: u32 = 3; // batch를 통째로 반영해볼 횟수
+const RETRY_BACKOFF: Duration = Duration::from_millis(100); // 재시도마다 두 배로 늘어남
+
+// 캐시 쓰기 요청. 큐에 들어온 순서대로 반영
+pub enum Write {
+    Insert(Box<Message>),
+    Update {
+        event: Box<MessageUpdateEvent>,
+        keep_revision: bool,
+    },
+    Delete(Vec<MessageId>),
+    Range {
+        channel_id: ChannelId,
+        range: database::Range,
+    },
+    Flush(oneshot::Sender<()>),
+}
+
+// 게이트웨이 이벤트마다 transaction을 열지 않도록 쓰기를 모아서 한 번에 반영
+// 큐가 가득 차면 send가 기다리므로 writer가 밀리면 이벤트 처리도 같이 늦춰짐
+#[derive(Clone)]
+pub struct Ingest {
+    tx: mpsc::Sender<Write>,
+}
+
+impl Ingest {
+    pub fn spawn(
+        storage: Arc<dyn Storage>,
+        settings: Settings,
+        live_ranges: Arc<DashMap<ChannelId, database::Range>>,
+        interval: Duration,
+        capacity: usize,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        tokio::spawn(run(storage, settings, live_ranges, rx, interval));
+        Self { tx }
+    }
+
+    pub async fn send(&self, write: Write) -> Result<(), Error> {
+        self.tx
+            .send(write)
+            .await
+            .map_err(|_| "ingest writer stopped")?;
+        Ok(())
+    }
+
+    // 지금까지 넣은 쓰기가 DB에 반영될 때까지 기다림
+    pub async fn flush(&self) -> Result<(), Error> {
+        let (done, wait) = oneshot::channel();
+        self.send(Write::Flush(done)).await?;
+        wait.await?;
+        Ok(())
+    }
+}
+
+async fn run(
+    storage: Arc<dyn Storage>,
+    settings: Settings,
+    live_ranges: Arc<DashMap<ChannelId, database::Range>>,
+    mut rx: mpsc::Receiver<Write>,
+    interval: Duration,
+) {
+    let mut batch = Vec::new();
+    // 채널마다 반영하지 못한 가장 최근 메세지. 이후 range는 그 다음부터만 기록
+    let mut dropped: HashMap<ChannelId, i64> = HashMap::new();
+    // 첫 쓰기가 들어온 뒤 interval 동안 모음. 큐가 닫히면 남은 것까지 반영하고 끝남
+    while let Some(first) = rx.recv().await {
+        let deadline = Instant::now() + interval;
+        let mut flush = matches!(first, Write::Flush(_));
+        batch.push(first);
+
+        while !flush && batch.len() < MAX_BATCH {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(write)) => {
+                    flush = matches!(write, Write::Flush(_));
+                    batch.push(write);
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        // 버린 쓰기가 있으면 기다리던 flush도 실패로 끝남
+        let (waiting, mut writes): (Vec<_>, Vec<_>) = std::mem::take(&mut batch)
+            .into_iter()
+            .partition(|write| matches!(write, Write::Flush(_)));
+        writes.retain_mut(|write| trim_dropped(write, &dropped));
+        let storage = storage.as_ref();
+        let applied = apply_with_retry(storage, &settings, &writes).await
+            || apply_each(storage, &settings, writes, &live_ranges, &mut dropped).await;
+        if applied {
+            for write in waiting {
+                if let Write::Flush(done) = write {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+}
+
+// 잠금, 연결 끊김 같은 일시적인 오류는 잠시 뒤 다시 시도
+async fn apply_with_retry(storage: &dyn Storage, settings: &Settings, writes: &[Write]) -> bool {
+    let mut backoff = RETRY_BACKOFF;
+    for attempt in 1..=RETRIES {
+        match apply_writes(storage, settings, writes).await {
+            Ok(()) => return true,
+            Err(e) => tracing::warn!(
+                "Failed to write {} cached events (attempt {}/{}): {}",
+                writes.len(),
+                attempt,
+                RETRIES,
+                e
+            ),
+        }
+        if attempt < RETRIES {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+    false
+}
+
+// 그래도 안 되면 하나씩 반영해서 실패한 쓰기만 버림
+// 버린 메세지가 캐싱된 구간에 들어가지 않도록 live range와 이후 range를 그 다음부터로 줄임
+async fn apply_each(
+    storage: &dyn Storage,
+    settings: &Settings,
+    writes: Vec<Write>,
+    live_ranges: &DashMap<ChannelId, database::Range>,
+    dropped: &mut HashMap<ChannelId, i64>,
+) -> bool {
+    let mut applied = true;
+    for mut write in writes {
+        if !trim_dropped(&mut write, dropped) {
+            continue;
+        }
+        let Err(e) = apply_writes(storage, settings, std::slice::from_ref(&write)).await else {
+            continue;
+        };
+        tracing::error!("Dropped a cached event: {}", e);
+        applied = false;
+        match write {
+            Write::Insert(msg) => {
+                let message_id = msg.id.get() as i64;
+                let last = dropped.entry(msg.channel_id).or_default();
+                *last = (*last).max(message_id);
+                let kept = live_ranges.get_mut(&msg.channel_id).map(|mut live| {
+                    live.clamp_start(message_id + 1)
+                        .map(|range| *live = range)
+                        .is_some()
+                });
+                if kept == Some(false) {
+                    live_ranges.remove(&msg.channel_id);
+                }
+            }
+            // DB에 없는 구간을 live range로 알려주지 않도록 세션을 새로 시작
+            Write::Range { channel_id, .. } => {
+                live_ranges.remove(&channel_id);
+            }
+            _ => {}
+        }
+    }
+    applied
+}
+
+// 가리기 규칙은 Settings에 서버별로 컴파일해둔 것을 씀
+async fn apply_writes(
+    storage: &dyn Storage,
+    settings: &Settings,
+    writes: &[Write],
+) -> Result<(), Error> {
+    let mut redactors = HashMap::new();
+    let guild_ids = writes.iter().filter_map(|write| match write {
+        Write::Insert(msg) => msg.guild_id,
+        Write::Update { event, .. } => event.guild_id,
+        _ => None,
+    });
+    for guild_id in guild_ids {
+        if let Entry::Vacant(entry) = redactors.entry(guild_id.get() as i64) {
+            entry.insert(settings.redactor(guild_id).await?);
+        }
+    }
+    storage.apply_writes(writes, &redactors).await
+}
+
+// 버린 메세지를 덮는 range는 그 다음부터로 줄임. 남는 게 없어 뺄 쓰기면 false
+fn trim_dropped(write: &mut Write, dropped: &HashMap<ChannelId, i64>) -> bool {
+    let Write::Range { channel_id, range } = write else {
+        return true;
+    };
+    let Some(message_id) = dropped.get(channel_id) else {
+        return true;
+    };
+    match range.clamp_start(message_id + 1) {
+        Some(clamped) => {
+            *range = clamped;
+            true
+        }
+        None => false,
+    }
+}
+
+// 저장소가 실행할 쓰기. 봇 필터와 가리기 규칙은 Batch::new에서 골라둠
+pub enum BatchWrite<'a> {
+    Insert {
+        guild_id: i64,
+        msg: &'a Message,
+        redactor: &'a Redactor,
+    },
+    Update {
+        event: &'a MessageUpdateEvent,
+        keep_revision: bool,
+        redactor: &'a Redactor,
+    },
+    Delete(&'a [MessageId]),
+}
+
+// 저장소와 상관없는 batch 처리. 저장소는 writes를 한 transaction에서 순서대로 실행하고
+// ranges를 더하기만 함
+pub struct Batch<'a> {
+    pub writes: Vec<BatchWrite<'a>>,
+    // 채널마다 병합은 batch당 한 번
+    pub ranges: HashMap<ChannelId, Vec<database::Range>>,
+}
+
+static NO_REDACTION: LazyLock<Redactor> = LazyLock::new(Redactor::default);
+
+impl<'a> Batch<'a> {
+    // Batch::new에 넘길 봇 필터를 읽어야 하는 채널
+    pub fn filter_channels(batch: &[Write]) -> Vec<ChannelId> {
+        batch
+            .iter()
+            .filter_map(|write| match write {
+                Write::Insert(msg) => Some(msg.channel_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn new(
+        batch: &'a [Write],
+        filters: &HashMap<ChannelId, BotFilter>,
+        redactors: &'a HashMap<i64, Arc<Redactor>>,
+    ) -> Self {
+        let mut writes = Vec::new();
+        let mut ranges: HashMap<ChannelId, Vec<database::Range>> = HashMap::new();
+        for write in batch {
+            match write {
+                Write::Insert(msg) => {
+                    let Some(guild_id) = msg.guild_id else {
+                        continue;
+                    };
+                    if filters[&msg.channel_id].allows(msg) {
+                        let guild_id = guild_id.get() as i64;
+                        writes.push(BatchWrite::Insert {
+                            guild_id,
+                            msg,
+                            redactor: &redactors[&guild_id],
+                        });
+                    }
+                }
+                Write::Update {
+                    event,
+                    keep_revision,
+                } => {
+                    // guild_id가 없는 update는 DM이라 캐시된 메세지가 없음
+                    let redactor = event
+                        .guild_id
+                        .and_then(|guild_id| redactors.get(&(guild_id.get() as i64)))
+                        .map_or(&*NO_REDACTION, Arc::as_ref);
+                    writes.push(BatchWrite::Update {
+                        event,
+                        keep_revision: *keep_revision,
+                        redactor,
+                    });
+                }
+                Write::Delete(message_ids) => writes.push(BatchWrite::Delete(message_ids)),
+                Write::Range { channel_id, range } => {
+                    let merged = ranges.entry(*channel_id).or_default();
+                    *merged = database::merge_ranges(std::mem::take(merged), *range);
+                }
+                Write::Flush(_) => {}
+            }
+        }
+        Batch { writes, ranges }
+    }
+}
+
+// SQLite 저장소의 batch 반영. Flush는 run에서 처리
+pub async fn apply(
+    pool: &SqlitePool,
+    batch: &[Write],
+    redactors: &HashMap<i64, Arc<Redactor>>,
+) -> Result<(), Error> {
+    let filters = database::load_bot_filters(pool, &Batch::filter_channels(batch)).await?;
+    let batch = Batch::new(batch, &filters, redactors);
+
+    let mut tx = pool.begin().await?;
+    for write in batch.writes {
+        match write {
+            BatchWrite::Insert {
+                guild_id,
+                msg,
+                redactor,
+            } => database::insert_message_in(&mut tx, guild_id, msg, redactor).await?,
+            BatchWrite::Update {
+                event,
+                keep_revision,
+                redactor,
+            } => database::update_message_in(&mut tx, event, keep_revision, redactor).await?,
+            BatchWrite::Delete(message_ids) => {
+                for message_id in message_ids {
+                    database::delete_message_in(&mut tx, *message_id).await?;
+                }
+            }
+        }
+    }
+    for (channel_id, ranges) in batch.ranges {
+        for range in ranges {
+            database::add_sync_range_in(&mut tx, channel_id.get() as i64, range).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStorage;
+    use poise::serenity_prelude::{GuildId, MessageId};
+
+    fn message(id: u64) -> Box<Message> {
+        let mut msg = Message::default();
+        msg.id = MessageId::new(id);
+        msg.channel_id = ChannelId::new(10);
+        msg.guild_id = Some(GuildId::new(100));
+        Box::new(msg)
+    }
+
+    #[tokio::test]
+    async fn batch_applies_in_order() {
+        let pool = database::memory_pool().await;
+        let storage = Arc::new(SqliteStorage::new(pool.clone()));
+        let settings = Settings::new(storage.clone());
+        let ingest = Ingest::spawn(
+            storage,
+            settings,
+            Arc::default(),
+            Duration::from_millis(10),
+            16,
+        );
+
+        for id in 1..=3 {
+            ingest.send(Write::Insert(message(id))).await.unwrap();
+            ingest
+                .send(Write::Range {
+                    channel_id: ChannelId::new(10),
+                    range: database::Range::new(1, id as i64),
+                })
+                .await
+                .unwrap();
+        }
+        ingest
+            .send(Write::Delete(vec![MessageId::new(2)]))
+            .await
+            .unwrap();
+        ingest.flush().await.unwrap();
+
+        let ids = database::list_message_ids_range(&pool, 10, 0, i64::MAX)
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&2));
+        assert_eq!(
+            database::latest_sync_range(&pool, 10).await.unwrap(),
+            Some(database::Range::new(1, 3))
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_write_is_dropped_alone() {
+        let pool = database::memory_pool().await;
+        sqlx::query(
+            "CREATE TRIGGER fail_insert BEFORE INSERT ON messages WHEN NEW.message_id = 2
+             BEGIN SELECT RAISE(ABORT, 'fail'); END",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let storage = Arc::new(SqliteStorage::new(pool.clone()));
+        let live_ranges: Arc<DashMap<_, _>> = Arc::default();
+        let settings = Settings::new(storage.clone());
+        let ingest = Ingest::spawn(
+            storage,
+            settings,
+            live_ranges.clone(),
+            Duration::from_millis(10),
+            16,
+        );
+
+        let channel_id = ChannelId::new(10);
+        live_ranges.insert(channel_id, database::Range::new(1, 3));
+        for id in 1..=3 {
+            ingest.send(Write::Insert(message(id))).await.unwrap();
+            ingest
+                .send(Write::Range {
+                    channel_id,
+                    range: database::Range::new(1, id as i64),
+                })
+                .await
+                .unwrap();
+        }
+        // 버린 쓰기가 있으면 flush는 실패
+        assert!(ingest.flush().await.is_err());
+
+        let ids = database::list_message_ids_range(&pool, 10, 0, i64::MAX)
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&2));
+        // 반영하지 못한 메세지는 캐싱된 구간에 들어가지 않음
+        assert_eq!(
+            database::list_sync_ranges(&pool, 10).await.unwrap(),
+            vec![database::Range::new(1, 1), database::Range::new(3, 3)]
+        );
+        assert_eq!(
+            *live_ranges.get(&channel_id).unwrap(),
+            database::Range::new(3, 3)
+        );
+
+        // 이후 batch도 버린 메세지 앞까지는 range로 기록하지 않음
+        ingest
+            .send(Write::Range {
+                channel_id,
+                range: database::Range::new(1, 4),
+            })
+            .await
+            .unwrap();
+        ingest.flush().await.unwrap();
+        assert_eq!(
+            database::list_sync_ranges(&pool, 10).await.unwrap(),
+            vec![database::Range::new(1, 1), database::Range::new(3, 4)]
+        );
+    }
+
+    #[test]
+    fn batch_filters_bots_and_merges_ranges() {
+        let mut bot = message(2);
+        bot.author.bot = true;
+        let mut dm = message(3);
+        dm.guild_id = None;
+        let range = |start, end| Write::Range {
+            channel_id: ChannelId::new(10),
+            range: database::Range::new(start, end),
+        };
+        let writes = vec![
+            Write::Insert(message(1)),
+            Write::Insert(bot),
+            Write::Insert(dm),
+            range(1, 1),
+            range(2, 3),
+            range(5, 6),
+        ];
+        let filters = HashMap::from([(ChannelId::new(10), BotFilter::default())]);
+        let redactors = HashMap::from([(100, Arc::new(Redactor::default()))]);
+
+        let batch = Batch::new(&writes, &filters, &redactors);
+
+        assert_eq!(batch.writes.len(), 1);
+        assert!(matches!(
+            batch.writes[0],
+            BatchWrite::Insert { guild_id: 100, msg, .. } if msg.id == MessageId::new(1)
+        ));
+        assert_eq!(
+            batch.ranges[&ChannelId::new(10)],
+            vec![database::Range::new(1, 3), database::Range::new(5, 6)]
+        );
+    }
+}
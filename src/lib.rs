@@ -0,0 +1,36 @@
+// 봇 바이너리(main.rs)와 benches가 같이 쓰는 모듈
+pub mod archive;
+pub mod backfill;
+pub mod backup;
+pub mod caching;
+pub mod cli;
+pub mod command;
+pub mod config;
+pub mod database;
+pub mod event;
+pub mod import;
+pub mod ingest;
+pub mod maintenance;
+pub mod postgres;
+pub mod reconcile;
+pub mod redaction;
+pub mod retention;
+pub mod settings;
+pub mod shard;
+pub mod storage;
+
+use dashmap::DashMap;
+use poise::serenity_prelude::ChannelId;
+use std::sync::Arc;
+
+pub struct Data {
+    pub storage: Arc<dyn storage::Storage>,
+    // 채널의 이벤트는 그 서버를 맡은 shard 하나로만 오므로 shard끼리 겹치지 않음
+    pub live_ranges: Arc<DashMap<ChannelId, database::Range>>,
+    pub ingest: ingest::Ingest,
+    pub settings: settings::Settings,
+    // SQLite 저장소일 때만
+    pub maintenance: Option<maintenance::Maintenance>,
+}
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Context<'a> = poise::Context<'a, Data, Error>;
@@ -1,24 +1,24 @@
 use poise::serenity_prelude as serenity;
 
-mod command;
-mod database;
-mod event;
-
 use dashmap::DashMap;
-use poise::serenity_prelude::ChannelId;
-use sqlx::SqlitePool;
+use discord_search_bot::{
+    Data, backfill, backup, cli, command, config, event, ingest, maintenance, retention, settings,
+    shard, storage,
+};
+use std::sync::Arc;
 use std::time::Duration;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-pub struct Data {
-    pub database: SqlitePool,
-    pub live_ranges: DashMap<ChannelId, database::Range>,
-}
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
-pub type Context<'a> = poise::Context<'a, Data, Error>;
-
 #[tokio::main]
 async fn main() {
+    let config = match config::Config::load() {
+        Ok(config) => config::init(config),
+        Err(e) => {
+            eprintln!("Invalid configuration:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Initialize logging
     let file_appender = tracing_appender::rolling::daily("logs", "discord_bot.log");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
@@ -33,32 +33,56 @@ async fn main() {
                 .with_writer(non_blocking)
                 .with_ansi(false)
         )
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "discord_search_bot=info,warn".into()),
-        )
+        .with(tracing_subscriber::EnvFilter::new(&config.log.filter))
         .init();
 
     tracing::info!("SearchBot start");
 
-    let token = std::env::args().nth(1).unwrap_or_else(|| {
-        let key = if cfg!(debug_assertions) {
-            "DISCORD_TOKEN_DEBUG"
-        } else {
-            "DISCORD_TOKEN"
-        };
-        std::env::var(key).expect("missing DISCORD_TOKEN and no token argument provided")
-    });
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = cli::run(&args).await {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // 개발 모드에서는 discord.token_debug(DISCORD_TOKEN_DEBUG)를 씀
+    let token = args
+        .first()
+        .cloned()
+        .or_else(|| config.token().map(str::to_owned))
+        .expect("missing DISCORD_TOKEN and no token argument provided");
+
+    let sharding = config.shard.sharding;
+    // 여러 프로세스로 shard를 나누면 전역 작업은 shard 0을 띄운 프로세스에서만
+    let global_jobs = sharding.runs_global_jobs();
 
     // Initialize database
-    let database = database::init_db()
+    let storage = storage::connect()
         .await
         .expect("Failed to initialize database");
-    tracing::info!("Database initialized");
+    tracing::info!("Database initialized ({})", storage.backend());
+
+    // 실시간 캐싱 쓰기를 모아서 반영하는 writer
+    let live_ranges = Arc::new(DashMap::new());
+    let settings = settings::Settings::new(storage.clone());
+    let ingest = ingest::Ingest::spawn(
+        storage.clone(),
+        settings.clone(),
+        live_ranges.clone(),
+        Duration::from_millis(config.ingest.flush_ms),
+        config.ingest.queue_size,
+    );
+    let data_ingest = ingest.clone();
 
     // Add MESSAGE_CONTENT intent for caching
-    let intents =
+    let mut intents =
         serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
+    // 별명 변경을 받으려면 developer portal에서 Server Members Intent를 켜야 함
+    if config.discord.members_intent {
+        intents |= serenity::GatewayIntents::GUILD_MEMBERS;
+    }
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
@@ -70,79 +94,179 @@ async fn main() {
         })
         .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
-                if !cfg!(debug_assertions) {
+                if !config.discord.dev_mode && global_jobs {
                     tracing::info!("Production mode: Registering commands globally");
                     poise::builtins::register_globally(ctx, &framework.options().commands).await?;
                 }
-                let pool = database.clone();
-                let http = ctx.http.clone();
-                tokio::spawn(async move {
-                    let interval_secs = std::env::var("VERSION_CHECK_INTERVAL_SECS")
-                        .ok()
-                        .and_then(|v| v.parse::<u64>().ok())
-                        .unwrap_or(86_400);
-                    if interval_secs == 0 {
-                        tracing::info!("Version polling disabled");
-                        return;
-                    }
-                    let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
-                    loop {
-                        ticker.tick().await;
-                        let latest = match crate::command::check_latest_version().await {
-                            Ok(Some(v)) => v,
-                            Ok(None) => continue,
-                            Err(e) => {
-                                tracing::warn!("version polling failed: {}", e);
-                                continue;
-                            }
-                        };
-                        let subscribers = match crate::database::list_version_subscribers(&pool).await {
-                            Ok(v) => v,
-                            Err(e) => {
-                                tracing::warn!("list subscribers failed: {}", e);
-                                continue;
+                // 기록 채우기, 백업, DB 정리는 SQLite 파일을 직접 다룸
+                // /maintenance는 어느 프로세스에서든 실행할 수 있고 주기 작업만 shard 0에서 돌림
+                let maintenance = storage
+                    .sqlite()
+                    .map(|database| maintenance::Maintenance::new(database.clone()));
+                if !global_jobs {
+                    tracing::info!("Global jobs run in the process with shard 0, skipped");
+                } else if let (Some(database), Some(scheduled)) =
+                    (storage.sqlite(), maintenance.clone())
+                {
+                    let pool = database.clone();
+                    let http = ctx.http.clone();
+                    let backfill_settings = settings.clone();
+                    tokio::spawn(async move {
+                        let interval_ms = config.tasks.backfill_interval_ms;
+                        if interval_ms == 0 {
+                            tracing::info!("History backfill disabled");
+                            return;
+                        }
+                        let interval = Duration::from_millis(interval_ms);
+                        backfill::run(pool, http, backfill_settings, interval).await;
+                    });
+                    if let Some(dir) = config.backup.dir.clone() {
+                        let pool = database.clone();
+                        tokio::spawn(async move {
+                            let interval_secs = config.backup.interval_secs;
+                            if interval_secs == 0 {
+                                tracing::info!("Scheduled backups disabled");
+                                return;
                             }
-                        };
-                        for user_id in subscribers {
-                            let uid = user_id as u64;
-                            let should_notify = match crate::database::should_notify_version(&pool, uid, &latest).await {
-                                Ok(v) => v,
+                            let interval = Duration::from_secs(interval_secs);
+                            backup::run(pool, dir, config.backup.keep, interval).await;
+                        });
+                    }
+                    tokio::spawn(async move {
+                        let interval_secs = config.tasks.maintenance_interval_secs;
+                        if interval_secs == 0 {
+                            tracing::info!("Database maintenance disabled");
+                            return;
+                        }
+                        scheduled.run(Duration::from_secs(interval_secs)).await;
+                    });
+                } else {
+                    tracing::info!("Backfill, backups and maintenance require SQLite, disabled");
+                }
+                if global_jobs {
+                    let retention_storage = storage.clone();
+                    tokio::spawn(async move {
+                        let interval_secs = config.tasks.retention_interval_secs;
+                        if interval_secs == 0 {
+                            tracing::info!("Retention pruning disabled");
+                            return;
+                        }
+                        retention::run(retention_storage, Duration::from_secs(interval_secs)).await;
+                    });
+                    let version_storage = storage.clone();
+                    let http = ctx.http.clone();
+                    tokio::spawn(async move {
+                        let interval_secs = config.tasks.version_check_interval_secs;
+                        if interval_secs == 0 {
+                            tracing::info!("Version polling disabled");
+                            return;
+                        }
+                        let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+                        loop {
+                            ticker.tick().await;
+                            let latest = match crate::command::check_latest_version().await {
+                                Ok(Some(v)) => v,
+                                Ok(None) => continue,
                                 Err(e) => {
-                                    tracing::warn!("should_notify_version failed for {}: {}", uid, e);
-                                    false
+                                    tracing::warn!("version polling failed: {}", e);
+                                    continue;
                                 }
                             };
-                            if !should_notify {
-                                continue;
-                            }
-                            let user = match poise::serenity_prelude::UserId::new(uid).to_user(&http).await {
-                                Ok(u) => u,
-                                Err(_) => continue,
+                            let subscribers = match version_storage.list_version_subscribers().await {
+                                Ok(v) => v,
+                                Err(e) => {
+                                    tracing::warn!("list subscribers failed: {}", e);
+                                    continue;
+                                }
                             };
-                            let dm = user.dm(
-                                &http,
-                                poise::serenity_prelude::CreateMessage::new().content(format!(
-                                    "새 버전 `{}` 이 감지되었습니다. `/version`으로 확인해보세요.",
-                                    latest
-                                )),
-                            ).await;
-                            if dm.is_ok() {
-                                let _ = crate::database::mark_version_notified(&pool, uid, &latest).await;
+                            for user_id in subscribers {
+                                let uid = user_id as u64;
+                                let should_notify = match version_storage.should_notify_version(uid, &latest).await {
+                                    Ok(v) => v,
+                                    Err(e) => {
+                                        tracing::warn!("should_notify_version failed for {}: {}", uid, e);
+                                        false
+                                    }
+                                };
+                                if !should_notify {
+                                    continue;
+                                }
+                                let user = match poise::serenity_prelude::UserId::new(uid).to_user(&http).await {
+                                    Ok(u) => u,
+                                    Err(_) => continue,
+                                };
+                                let dm = user.dm(
+                                    &http,
+                                    poise::serenity_prelude::CreateMessage::new().content(format!(
+                                        "새 버전 `{}` 이 감지되었습니다. `/version`으로 확인해보세요.",
+                                        latest
+                                    )),
+                                ).await;
+                                if dm.is_ok() {
+                                    let _ = version_storage.mark_version_notified(uid, &latest).await;
+                                }
                             }
                         }
-                    }
-                });
+                    });
+                }
                 Ok(Data {
-                    database,
-                    live_ranges: DashMap::new(),
+                    settings,
+                    storage,
+                    live_ranges,
+                    ingest: data_ingest,
+                    maintenance,
                 })
             })
         })
         .build();
 
-    let client = serenity::ClientBuilder::new(token, intents)
+    let mut client = serenity::ClientBuilder::new(token, intents)
         .framework(framework)
-        .await;
+        .await
+        .unwrap();
+
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down");
+        shard_manager.shutdown_all().await;
+    });
+
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        let interval_secs = config.shard.status_interval_secs;
+        if interval_secs == 0 {
+            tracing::info!("Shard status logging disabled");
+            return;
+        }
+        shard::log_status(shard_manager, Duration::from_secs(interval_secs)).await;
+    });
+
+    tracing::info!("Starting {}", sharding);
+    sharding.start(&mut client).await.unwrap();
+
+    // 종료 전에 큐에 남은 캐시 쓰기 반영
+    if let Err(e) = ingest.flush().await {
+        tracing::error!("Failed to flush cache writes: {}", e);
+    }
+}
+
+// Ctrl+C 또는 docker stop
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    client.unwrap().start().await.unwrap();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
 }
@@ -0,0 +1,165 @@
+use crate::database::{self, Vacuum};
+use sqlx::SqlitePool;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// 빈 페이지가 이 비율 이상일 때만 VACUUM
+const VACUUM_FREE_RATIO: f64 = 0.1;
+
+#[derive(Debug)]
+pub struct Step {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    pub result: Result<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub steps: Vec<Step>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.steps.iter().all(|step| step.result.is_ok())
+    }
+
+    // 한 단계가 실패해도 나머지는 계속
+    async fn push(
+        &mut self,
+        name: &'static str,
+        step: impl Future<Output = Result<String, crate::Error>>,
+    ) {
+        let start = Instant::now();
+        let result = step.await.map_err(|e| e.to_string());
+        let elapsed = start.elapsed();
+        match &result {
+            Ok(_) => tracing::info!("Maintenance {} took {:?}", name, elapsed),
+            Err(e) => tracing::warn!("Maintenance {} failed after {:?}: {}", name, elapsed, e),
+        }
+        self.steps.push(Step {
+            name,
+            elapsed,
+            result,
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            let (status, detail) = match &step.result {
+                Ok(detail) => ("ok", detail),
+                Err(e) => ("failed", e),
+            };
+            writeln!(
+                f,
+                "{}: {} ({:.1}s) {}",
+                step.name,
+                status,
+                step.elapsed.as_secs_f64(),
+                detail
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// 캐시 DB는 계속 커지기만 하므로 주기적으로 색인 정리, 검사, 빈 공간 회수, 통계 갱신
+// 주기 작업과 명령이 겹치지 않도록 한 번에 하나만 실행
+#[derive(Clone)]
+pub struct Maintenance {
+    pool: SqlitePool,
+    running: Arc<Mutex<()>>,
+}
+
+impl Maintenance {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            running: Arc::new(Mutex::new(())),
+        }
+    }
+
+    // 첫 실행은 interval 뒤. 시작할 때마다 VACUUM이 돌지 않도록
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            ticker.tick().await;
+            match self.run_once().await {
+                Some(report) if !report.is_ok() => {
+                    tracing::warn!("Database maintenance finished with failures")
+                }
+                Some(_) => tracing::info!("Database maintenance finished"),
+                None => tracing::info!("Database maintenance already running, skipped"),
+            }
+        }
+    }
+
+    // 이미 실행 중이면 None
+    pub async fn run_once(&self) -> Option<Report> {
+        let _guard = self.running.try_lock().ok()?;
+        let pool = &self.pool;
+        let mut report = Report::default();
+
+        report
+            .push("fts optimize", async {
+                database::optimize_fts(pool).await?;
+                Ok(String::new())
+            })
+            .await;
+        report
+            .push("integrity_check", async {
+                let problems = database::integrity_check(pool).await?;
+                if problems.is_empty() {
+                    Ok(String::new())
+                } else {
+                    Err(problems.join("; ").into())
+                }
+            })
+            .await;
+        report
+            .push("vacuum", async {
+                Ok(match database::vacuum(pool, VACUUM_FREE_RATIO).await? {
+                    Vacuum::Incremental => "incremental".to_owned(),
+                    Vacuum::Full => "full".to_owned(),
+                    Vacuum::Skipped => "skipped, little free space".to_owned(),
+                })
+            })
+            .await;
+        report
+            .push("analyze", async {
+                database::analyze(pool).await?;
+                Ok(String::new())
+            })
+            .await;
+
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_every_step() {
+        let maintenance = Maintenance::new(database::memory_pool().await);
+        let report = maintenance.run_once().await.unwrap();
+
+        let names: Vec<_> = report.steps.iter().map(|step| step.name).collect();
+        assert_eq!(
+            names,
+            vec!["fts optimize", "integrity_check", "vacuum", "analyze"]
+        );
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[tokio::test]
+    async fn skips_while_running() {
+        let maintenance = Maintenance::new(database::memory_pool().await);
+        let _guard = maintenance.running.lock().await;
+        assert!(maintenance.run_once().await.is_none());
+    }
+}
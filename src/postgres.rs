@@ -0,0 +1,927 @@
+use crate::Error;
+use crate::database::{
+    self, BotFilter, CacheStats, MessageRevision, Range, ScopeSettings, SearchResult, Setting,
+    SettingScope, SettingValue, UserData, UserMessage, UserRevision,
+};
+use crate::ingest::{Batch, BatchWrite, Write};
+use crate::redaction::Redactor;
+use crate::storage::Storage;
+use async_trait::async_trait;
+use poise::serenity_prelude::{
+    ChannelId, GuildId, GuildMemberUpdateEvent, Message, MessageId, MessageUpdateEvent, UserId,
+};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{PgConnection, PgPool};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+// 여러 봇 프로세스나 다른 서비스와 DB를 같이 쓸 때. 스키마는 migrations_postgres
+// 검색은 SQLite와 같은 부분 문자열 검색(ILIKE)이고 pg_trgm GIN index를 씀
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+const MESSAGE_COLUMNS: &str = r#"
+    SELECT m.message_id, m.channel_id, m.guild_id, m.author_id, m.author_name, m.content, m.created_at, m.reply_to_id,
+           COALESCE(mem.nick, mem.global_name) AS display_name, mem.avatar_url
+    FROM messages m
+    LEFT JOIN members mem ON mem.guild_id = m.guild_id AND mem.user_id = m.author_id
+"#;
+
+impl PostgresStorage {
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let pool = PgPoolOptions::new().max_connections(5).connect(url).await?;
+        sqlx::migrate!("./migrations_postgres").run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    // transaction 열기 전에 설정을 읽어둠. SQLite 쪽 load_bot_filters와 같음
+    async fn load_bot_filters(
+        &self,
+        channel_ids: &[ChannelId],
+    ) -> Result<HashMap<ChannelId, BotFilter>, sqlx::Error> {
+        let mut filters = HashMap::new();
+        for channel_id in channel_ids {
+            if let Entry::Vacant(entry) = filters.entry(*channel_id) {
+                let settings = self
+                    .load_settings(SettingScope::Channel(*channel_id))
+                    .await?;
+                entry.insert(settings.bot_filter());
+            }
+        }
+        Ok(filters)
+    }
+}
+
+async fn insert_message_in(
+    conn: &mut PgConnection,
+    guild_id: i64,
+    msg: &Message,
+    redactor: &Redactor,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO messages (message_id, channel_id, guild_id, author_id, author_name, content, created_at, reply_to_id)
+        SELECT $1, $2, $3, $4, $5, $6, $7, $8
+        WHERE NOT EXISTS (SELECT 1 FROM opted_out_users WHERE user_id = $4)
+        ON CONFLICT (message_id) DO UPDATE SET
+            channel_id = excluded.channel_id,
+            guild_id = excluded.guild_id,
+            author_id = excluded.author_id,
+            author_name = excluded.author_name,
+            content = excluded.content,
+            created_at = excluded.created_at,
+            reply_to_id = excluded.reply_to_id
+        "#,
+    )
+    .bind(msg.id.get() as i64)
+    .bind(msg.channel_id.get() as i64)
+    .bind(guild_id)
+    .bind(msg.author.id.get() as i64)
+    .bind(&msg.author.name)
+    .bind(redactor.redact(&msg.content).as_ref())
+    .bind(msg.timestamp.timestamp())
+    .bind(database::reply_to_id(msg))
+    .execute(&mut *conn)
+    .await?;
+
+    if msg.webhook_id.is_some() {
+        return Ok(()); // 웹훅은 메세지마다 이름이 다름
+    }
+    let nick = msg.member.as_ref().map(|m| m.nick.clone());
+    sqlx::query(
+        r#"
+        INSERT INTO members (guild_id, user_id, username, global_name, nick, avatar_url, updated_at)
+        SELECT $1, $2, $3, $4, $5, $6, $7
+        WHERE NOT EXISTS (SELECT 1 FROM opted_out_users WHERE user_id = $2)
+        ON CONFLICT (guild_id, user_id) DO UPDATE SET
+            username = excluded.username,
+            global_name = excluded.global_name,
+            nick = CASE WHEN $8 THEN excluded.nick ELSE members.nick END,
+            avatar_url = excluded.avatar_url,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(guild_id)
+    .bind(msg.author.id.get() as i64)
+    .bind(&msg.author.name)
+    .bind(&msg.author.global_name)
+    .bind(nick.clone().flatten())
+    .bind(msg.author.face())
+    .bind(chrono::Utc::now().timestamp())
+    .bind(nick.is_some())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn update_message_in(
+    conn: &mut PgConnection,
+    event: &MessageUpdateEvent,
+    keep_revision: bool,
+    redactor: &Redactor,
+) -> Result<(), sqlx::Error> {
+    let Some(content) = &event.content else {
+        return Ok(());
+    };
+    let content = redactor.redact(content);
+    let message_id = event.id.get() as i64;
+
+    if keep_revision {
+        let edited_at = event
+            .edited_timestamp
+            .map(|t| t.unix_timestamp())
+            .unwrap_or_else(|| chrono::Utc::now().timestamp());
+        sqlx::query(
+            r#"
+            INSERT INTO message_revisions (message_id, channel_id, guild_id, content, edited_at)
+            SELECT message_id, channel_id, guild_id, content, $1
+            FROM messages
+            WHERE message_id = $2 AND content <> $3
+            "#,
+        )
+        .bind(edited_at)
+        .bind(message_id)
+        .bind(content.as_ref())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query("UPDATE messages SET content = $1 WHERE message_id = $2")
+        .bind(content.as_ref())
+        .bind(message_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+// 같은 채널의 range를 동시에 병합하지 않도록 transaction이 끝날 때까지 채널 단위로 잠금
+async fn add_sync_range_in(
+    conn: &mut PgConnection,
+    channel_id: i64,
+    new_range: Range,
+) -> Result<Range, sqlx::Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(channel_id)
+        .execute(&mut *conn)
+        .await?;
+    let rows: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT start_id, end_id FROM sync_ranges
+         WHERE channel_id = $1 AND start_id <= $2 AND end_id >= $3",
+    )
+    .bind(channel_id)
+    .bind(new_range.end.saturating_add(1))
+    .bind(new_range.start.saturating_sub(1))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut merged = new_range;
+    for (start, end) in &rows {
+        let existing = Range::new(*start, *end);
+        if existing.contains(&new_range) {
+            return Ok(existing);
+        }
+        merged = merged.merge(&existing).unwrap_or(merged);
+    }
+
+    for (start, _) in &rows {
+        sqlx::query("DELETE FROM sync_ranges WHERE channel_id = $1 AND start_id = $2")
+            .bind(channel_id)
+            .bind(start)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("INSERT INTO sync_ranges (channel_id, start_id, end_id) VALUES ($1, $2, $3)")
+        .bind(channel_id)
+        .bind(merged.start)
+        .bind(merged.end)
+        .execute(&mut *conn)
+        .await?;
+    Ok(merged)
+}
+
+async fn forget_channel_in(conn: &mut PgConnection, channel_id: i64) -> Result<(), sqlx::Error> {
+    for sql in [
+        "DELETE FROM messages WHERE channel_id = $1",
+        "DELETE FROM message_revisions WHERE channel_id = $1",
+        "DELETE FROM sync_ranges WHERE channel_id = $1",
+        "DELETE FROM settings WHERE channel_id = $1",
+    ] {
+        sqlx::query(sql)
+            .bind(channel_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn database_size(&self) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT pg_database_size(current_database())")
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn apply_writes(
+        &self,
+        batch: &[Write],
+        redactors: &HashMap<i64, Arc<Redactor>>,
+    ) -> Result<(), Error> {
+        let filters = self
+            .load_bot_filters(&Batch::filter_channels(batch))
+            .await?;
+        let batch = Batch::new(batch, &filters, redactors);
+
+        let mut tx = self.pool.begin().await?;
+        for write in batch.writes {
+            match write {
+                BatchWrite::Insert {
+                    guild_id,
+                    msg,
+                    redactor,
+                } => insert_message_in(&mut tx, guild_id, msg, redactor).await?,
+                BatchWrite::Update {
+                    event,
+                    keep_revision,
+                    redactor,
+                } => update_message_in(&mut tx, event, keep_revision, redactor).await?,
+                BatchWrite::Delete(message_ids) => {
+                    let ids: Vec<i64> = message_ids.iter().map(|id| id.get() as i64).collect();
+                    sqlx::query("DELETE FROM messages WHERE message_id = ANY($1)")
+                        .bind(ids)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+        }
+        for (channel_id, ranges) in batch.ranges {
+            for range in ranges {
+                add_sync_range_in(&mut tx, channel_id.get() as i64, range).await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_messages(
+        &self,
+        msgs: &[Message],
+        guild_id: i64,
+        redactor: &Redactor,
+    ) -> Result<(), sqlx::Error> {
+        let channel_ids: Vec<_> = msgs.iter().map(|msg| msg.channel_id).collect();
+        let filters = self.load_bot_filters(&channel_ids).await?;
+
+        let mut tx = self.pool.begin().await?;
+        for msg in msgs
+            .iter()
+            .filter(|msg| filters[&msg.channel_id].allows(msg))
+        {
+            insert_message_in(&mut tx, guild_id, msg, redactor).await?;
+        }
+        tx.commit().await
+    }
+
+    async fn delete_message(&self, message_id: MessageId) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM messages WHERE message_id = $1")
+            .bind(message_id.get() as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_member(&self, event: &GuildMemberUpdateEvent) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE members
+            SET username = $1, global_name = $2, nick = $3, avatar_url = $4, updated_at = $5
+            WHERE guild_id = $6 AND user_id = $7
+            "#,
+        )
+        .bind(&event.user.name)
+        .bind(&event.user.global_name)
+        .bind(&event.nick)
+        .bind(event.user.face())
+        .bind(chrono::Utc::now().timestamp())
+        .bind(event.guild_id.get() as i64)
+        .bind(event.user.id.get() as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn search_messages_range(
+        &self,
+        guild_id: i64,
+        channel_id: i64,
+        query: &str,
+        min_id: i64,
+        max_id: i64,
+        limit: u32,
+        include_revisions: bool,
+    ) -> Result<Vec<SearchResult>, sqlx::Error> {
+        let query_pattern = format!("%{}%", query);
+        let sql = format!(
+            r#"{MESSAGE_COLUMNS}
+            WHERE m.guild_id = $1
+              AND m.channel_id = $2
+              AND m.message_id >= $3
+              AND m.message_id <= $4
+              AND (
+                m.content ILIKE $5
+                OR ($6 AND m.message_id IN (
+                    SELECT r.message_id FROM message_revisions r
+                    WHERE r.channel_id = m.channel_id AND r.content ILIKE $5
+                ))
+              )
+            ORDER BY m.message_id DESC
+            LIMIT $7"#
+        );
+        sqlx::query_as(&sql)
+            .bind(guild_id)
+            .bind(channel_id)
+            .bind(min_id)
+            .bind(max_id)
+            .bind(query_pattern)
+            .bind(include_revisions)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_message(&self, message_id: i64) -> Result<Option<SearchResult>, sqlx::Error> {
+        sqlx::query_as(&format!("{MESSAGE_COLUMNS} WHERE m.message_id = $1"))
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn get_replies(
+        &self,
+        message_id: i64,
+        limit: u32,
+    ) -> Result<Vec<SearchResult>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "{MESSAGE_COLUMNS} WHERE m.reply_to_id = $1 ORDER BY m.message_id LIMIT $2"
+        ))
+        .bind(message_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn list_message_revisions(
+        &self,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT content, edited_at FROM message_revisions WHERE message_id = $1 ORDER BY revision_id",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn messages_with_revisions(&self, message_ids: &[i64]) -> Result<Vec<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT DISTINCT message_id FROM message_revisions WHERE message_id = ANY($1)",
+        )
+        .bind(message_ids)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn delete_guild_revisions(&self, guild_id: GuildId) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM message_revisions WHERE guild_id = $1")
+            .bind(guild_id.get() as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn redact_guild_messages(
+        &self,
+        guild_id: GuildId,
+        redactor: &Redactor,
+    ) -> Result<u64, sqlx::Error> {
+        const PAGE_SIZE: i64 = 1_000;
+        if redactor.is_empty() {
+            return Ok(0);
+        }
+
+        let mut changed = 0;
+        for (select, update) in [
+            (
+                "SELECT message_id, content FROM messages
+                 WHERE guild_id = $1 AND message_id > $2 ORDER BY message_id LIMIT $3",
+                "UPDATE messages SET content = $1 WHERE message_id = $2",
+            ),
+            (
+                "SELECT revision_id, content FROM message_revisions
+                 WHERE guild_id = $1 AND revision_id > $2 ORDER BY revision_id LIMIT $3",
+                "UPDATE message_revisions SET content = $1 WHERE revision_id = $2",
+            ),
+        ] {
+            let mut after_id = 0;
+            loop {
+                let page: Vec<(i64, String)> = sqlx::query_as(select)
+                    .bind(guild_id.get() as i64)
+                    .bind(after_id)
+                    .bind(PAGE_SIZE)
+                    .fetch_all(&self.pool)
+                    .await?;
+                let Some((last_id, _)) = page.last() else {
+                    break;
+                };
+                after_id = *last_id;
+
+                let mut tx = self.pool.begin().await?;
+                for (id, content) in &page {
+                    if let Cow::Owned(redacted) = redactor.redact(content) {
+                        sqlx::query(update)
+                            .bind(redacted)
+                            .bind(id)
+                            .execute(&mut *tx)
+                            .await?;
+                        changed += 1;
+                    }
+                }
+                tx.commit().await?;
+            }
+        }
+        Ok(changed)
+    }
+
+    async fn count_channel_messages(&self, channel_id: ChannelId) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE channel_id = $1")
+            .bind(channel_id.get() as i64)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn channel_cache_stats(&self, channel_id: ChannelId) -> Result<CacheStats, sqlx::Error> {
+        let (count, earliest, latest) = sqlx::query_as(
+            "SELECT COUNT(*), MIN(created_at), MAX(created_at) FROM messages WHERE channel_id = $1",
+        )
+        .bind(channel_id.get() as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(CacheStats {
+            count,
+            earliest,
+            latest,
+        })
+    }
+
+    async fn list_message_ids_range(
+        &self,
+        channel_id: i64,
+        min_id: i64,
+        max_id: i64,
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT message_id FROM messages WHERE channel_id = $1 AND message_id >= $2 AND message_id <= $3",
+        )
+        .bind(channel_id)
+        .bind(min_id)
+        .bind(max_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn list_opted_out_users(&self, user_ids: &[i64]) -> Result<HashSet<i64>, sqlx::Error> {
+        let ids: Vec<i64> =
+            sqlx::query_scalar("SELECT user_id FROM opted_out_users WHERE user_id = ANY($1)")
+                .bind(user_ids)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(ids.into_iter().collect())
+    }
+
+    async fn forget_user(&self, user_id: UserId) -> Result<u64, sqlx::Error> {
+        let id = user_id.get() as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO opted_out_users (user_id, opted_out_at) VALUES ($1, $2)
+             ON CONFLICT (user_id) DO NOTHING",
+        )
+        .bind(id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+        let deleted = sqlx::query("DELETE FROM messages WHERE author_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM members WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn collect_user_data(&self, user_id: UserId) -> Result<UserData, sqlx::Error> {
+        let id = user_id.get() as i64;
+        let opted_out_at =
+            sqlx::query_scalar("SELECT opted_out_at FROM opted_out_users WHERE user_id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        let version_subscription = sqlx::query_as(
+            "SELECT enabled, last_notified_version FROM version_subscriptions WHERE user_id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let members = sqlx::query_as(
+            "SELECT guild_id, username, global_name, nick, avatar_url, updated_at
+             FROM members WHERE user_id = $1 ORDER BY guild_id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut messages: Vec<UserMessage> = sqlx::query_as(
+            "SELECT message_id, channel_id, guild_id, author_name, content, created_at, reply_to_id
+             FROM messages WHERE author_id = $1 ORDER BY message_id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let revisions: Vec<UserRevision> = sqlx::query_as(
+            "SELECT r.message_id, r.content, r.edited_at
+             FROM message_revisions r JOIN messages m ON m.message_id = r.message_id
+             WHERE m.author_id = $1 ORDER BY r.message_id, r.edited_at",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut by_message: HashMap<i64, Vec<UserRevision>> = HashMap::new();
+        for revision in revisions {
+            by_message
+                .entry(revision.message_id)
+                .or_default()
+                .push(revision);
+        }
+        for message in &mut messages {
+            message.revisions = by_message.remove(&message.message_id).unwrap_or_default();
+        }
+
+        Ok(UserData {
+            user_id: id,
+            exported_at: chrono::Utc::now().timestamp(),
+            opted_out_at,
+            version_subscription,
+            members,
+            messages,
+        })
+    }
+
+    // 기록 채우기가 없어서 sync range가 있는 채널만
+    async fn list_caching_channels(&self) -> Result<Vec<ChannelId>, sqlx::Error> {
+        let ids: Vec<i64> = sqlx::query_scalar("SELECT DISTINCT channel_id FROM sync_ranges")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(ids
+            .into_iter()
+            .map(|id| ChannelId::new(id as u64))
+            .collect())
+    }
+
+    async fn list_guild_cached_channels(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<ChannelId>, sqlx::Error> {
+        let ids: Vec<i64> =
+            sqlx::query_scalar("SELECT DISTINCT channel_id FROM messages WHERE guild_id = $1")
+                .bind(guild_id.get() as i64)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(ids
+            .into_iter()
+            .map(|id| ChannelId::new(id as u64))
+            .collect())
+    }
+
+    async fn purge_channel(&self, channel_id: ChannelId) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for sql in [
+            "DELETE FROM messages WHERE channel_id = $1",
+            "DELETE FROM sync_ranges WHERE channel_id = $1",
+        ] {
+            sqlx::query(sql)
+                .bind(channel_id.get() as i64)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn forget_channel(&self, channel_id: ChannelId) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        forget_channel_in(&mut tx, channel_id.get() as i64).await?;
+        tx.commit().await
+    }
+
+    async fn forget_guild(
+        &self,
+        guild_id: GuildId,
+        known_channels: &[ChannelId],
+    ) -> Result<(), sqlx::Error> {
+        let mut channels = self.list_guild_cached_channels(guild_id).await?;
+        channels.extend_from_slice(known_channels);
+        channels.sort();
+        channels.dedup();
+
+        let id = guild_id.get() as i64;
+        let mut tx = self.pool.begin().await?;
+        for channel_id in channels {
+            forget_channel_in(&mut tx, channel_id.get() as i64).await?;
+        }
+        for sql in [
+            "DELETE FROM messages WHERE guild_id = $1",
+            "DELETE FROM message_revisions WHERE guild_id = $1",
+            "DELETE FROM members WHERE guild_id = $1",
+            "DELETE FROM settings WHERE guild_id = $1",
+        ] {
+            sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+        }
+        tx.commit().await
+    }
+
+    async fn list_known_guilds(&self) -> Result<Vec<GuildId>, sqlx::Error> {
+        let ids: Vec<i64> = sqlx::query_scalar(
+            "SELECT guild_id FROM messages
+             UNION SELECT guild_id FROM message_revisions
+             UNION SELECT guild_id FROM members
+             UNION SELECT guild_id FROM settings
+             ORDER BY guild_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids
+            .into_iter()
+            .filter(|id| *id > 0)
+            .map(|id| GuildId::new(id as u64))
+            .collect())
+    }
+
+    async fn add_sync_range(
+        &self,
+        channel_id: i64,
+        start: i64,
+        end: i64,
+    ) -> Result<Range, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let merged = add_sync_range_in(&mut tx, channel_id, Range::new(start, end)).await?;
+        tx.commit().await?;
+        Ok(merged)
+    }
+
+    async fn latest_sync_range(&self, channel_id: i64) -> Result<Option<Range>, sqlx::Error> {
+        let row: Option<(i64, i64)> = sqlx::query_as(
+            "SELECT start_id, end_id FROM sync_ranges WHERE channel_id = $1 ORDER BY end_id DESC LIMIT 1",
+        )
+        .bind(channel_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|(start, end)| Range::new(start, end)))
+    }
+
+    async fn list_sync_ranges(&self, channel_id: i64) -> Result<Vec<Range>, sqlx::Error> {
+        let rows: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT start_id, end_id FROM sync_ranges WHERE channel_id = $1 ORDER BY start_id",
+        )
+        .bind(channel_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(start, end)| Range::new(start, end))
+            .collect())
+    }
+
+    async fn channel_guild_id(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result<Option<GuildId>, sqlx::Error> {
+        let id: Option<i64> =
+            sqlx::query_scalar("SELECT guild_id FROM messages WHERE channel_id = $1 LIMIT 1")
+                .bind(channel_id.get() as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(id.map(|id| GuildId::new(id as u64)))
+    }
+
+    async fn nth_newest_message_id(
+        &self,
+        channel_id: ChannelId,
+        n: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT message_id FROM messages WHERE channel_id = $1
+             ORDER BY message_id DESC LIMIT 1 OFFSET $2",
+        )
+        .bind(channel_id.get() as i64)
+        .bind(n - 1)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    // 수정 기록은 ad_messages_revisions trigger가 같이 지움
+    // range 병합과 겹치지 않도록 add_sync_range_in과 같은 잠금을 잡음
+    async fn prune_channel_before(
+        &self,
+        channel_id: ChannelId,
+        min_id: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let channel_id = channel_id.get() as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let deleted = sqlx::query("DELETE FROM messages WHERE channel_id = $1 AND message_id < $2")
+            .bind(channel_id)
+            .bind(min_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM sync_ranges WHERE channel_id = $1 AND end_id < $2")
+            .bind(channel_id)
+            .bind(min_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE sync_ranges SET start_id = $2 WHERE channel_id = $1 AND start_id < $2")
+            .bind(channel_id)
+            .bind(min_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn load_settings(&self, scope: SettingScope) -> Result<ScopeSettings, sqlx::Error> {
+        let rows = match scope {
+            SettingScope::Guild(guild_id) => sqlx::query_as(
+                "SELECT setting, bool_value, int_value, text_value FROM settings
+                 WHERE guild_id = $1 AND channel_id = 0",
+            )
+            .bind(guild_id.get() as i64),
+            SettingScope::Channel(channel_id) => sqlx::query_as(
+                "SELECT setting, bool_value, int_value, text_value FROM settings
+                 WHERE channel_id = $1",
+            )
+            .bind(channel_id.get() as i64),
+        }
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ScopeSettings::from_rows(rows))
+    }
+
+    async fn save_setting(
+        &self,
+        guild_id: GuildId,
+        scope: SettingScope,
+        setting: Setting,
+        value: Option<SettingValue>,
+    ) -> Result<(), sqlx::Error> {
+        let (guild_id, channel_id) = match scope {
+            SettingScope::Guild(guild_id) => (guild_id, 0),
+            SettingScope::Channel(channel_id) => (guild_id, channel_id.get() as i64),
+        };
+
+        let mut tx = self.pool.begin().await?;
+        match scope {
+            SettingScope::Guild(_) => sqlx::query(
+                "DELETE FROM settings WHERE guild_id = $1 AND channel_id = 0 AND setting = $2",
+            )
+            .bind(guild_id.get() as i64),
+            SettingScope::Channel(_) => {
+                sqlx::query("DELETE FROM settings WHERE channel_id = $1 AND setting = $2")
+                    .bind(channel_id)
+            }
+        }
+        .bind(setting.name())
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some(value) = value {
+            let (bool_value, int_value, text_value) = value.into_columns();
+            sqlx::query(
+                "INSERT INTO settings (guild_id, channel_id, setting, bool_value, int_value, text_value)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(channel_id)
+            .bind(setting.name())
+            .bind(bool_value)
+            .bind(int_value)
+            .bind(text_value)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn set_version_subscription(
+        &self,
+        user_id: u64,
+        enabled: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO version_subscriptions (user_id, enabled) VALUES ($1, $2)
+             ON CONFLICT (user_id) DO UPDATE SET enabled = excluded.enabled",
+        )
+        .bind(user_id as i64)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_version_subscribers(&self) -> Result<Vec<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT user_id FROM version_subscriptions WHERE enabled ORDER BY user_id",
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn should_notify_version(
+        &self,
+        user_id: u64,
+        latest_version: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let last: Option<Option<String>> = sqlx::query_scalar(
+            "SELECT last_notified_version FROM version_subscriptions WHERE user_id = $1 AND enabled",
+        )
+        .bind(user_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(last.is_some_and(|last| last.as_deref() != Some(latest_version)))
+    }
+
+    async fn mark_version_notified(
+        &self,
+        user_id: u64,
+        latest_version: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE version_subscriptions SET last_notified_version = $1 WHERE user_id = $2",
+        )
+        .bind(latest_version)
+        .bind(user_id as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl PostgresStorage {
+    // 테스트끼리 겹치지 않도록 새 schema에 migration을 적용. pg_trgm은 public에 있을 수 있어서 search_path에 같이 둠
+    pub async fn test_schema(url: &str) -> Result<(Self, String), sqlx::Error> {
+        use sqlx::ConnectOptions;
+        use sqlx::postgres::PgConnectOptions;
+        use std::str::FromStr;
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let schema = format!("test_{}_{}", std::process::id(), nanos);
+        let mut conn = PgConnectOptions::from_str(url)?.connect().await?;
+        sqlx::query(&format!("CREATE SCHEMA {schema}"))
+            .execute(&mut conn)
+            .await?;
+
+        let options =
+            PgConnectOptions::from_str(url)?.options([("search_path", format!("{schema},public"))]);
+        let pool = PgPoolOptions::new()
+            .max_connections(2)
+            .connect_with(options)
+            .await?;
+        sqlx::migrate!("./migrations_postgres").run(&pool).await?;
+        Ok((Self { pool }, schema))
+    }
+
+    pub async fn drop_schema(&self, schema: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(&format!("DROP SCHEMA {schema} CASCADE"))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
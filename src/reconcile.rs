@@ -0,0 +1,176 @@
+use crate::redaction::Redactor;
+use crate::settings::Settings;
+use crate::storage::Storage;
+use crate::{Error, database};
+use dashmap::DashMap;
+use poise::serenity_prelude::{Channel, ChannelId, GetMessages, GuildId, Http, MessageId};
+use std::cmp::{max, min};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+const PAGE_SIZE: u8 = 100; // discord api limit
+const MAX_FORWARD_PAGES: usize = 50; // 꺼져있던 동안 쌓인 메세지. 남은건 검색, 백필이 채움
+const RECHECK_PAGES: usize = 5; // 최근 500개 메세지의 수정, 삭제 확인
+
+// 봇이 꺼져있던 동안 놓친 새 메세지, 수정, 삭제를 캐시에 반영하고
+// 최신 메세지까지 이어진 구간을 live range로 복구
+// shard마다 자기가 맡은 서버의 채널만 처리
+pub async fn run(
+    storage: Arc<dyn Storage>,
+    settings: Settings,
+    http: Arc<Http>,
+    live_ranges: Arc<DashMap<ChannelId, database::Range>>,
+    guilds: &HashSet<GuildId>,
+) {
+    let mut channels = Vec::new();
+    for guild_id in guilds {
+        match storage.list_guild_cached_channels(*guild_id).await {
+            Ok(guild_channels) => channels.extend(guild_channels),
+            Err(e) => tracing::warn!("list_guild_cached_channels failed for {}: {}", guild_id, e),
+        }
+    }
+
+    tracing::info!("Reconciling {} caching channels", channels.len());
+    for channel_id in channels {
+        if let Err(e) =
+            reconcile_channel(storage.as_ref(), &settings, &http, &live_ranges, channel_id).await
+        {
+            tracing::warn!("reconcile failed for channel {}: {}", channel_id, e);
+        }
+    }
+    tracing::info!("Reconcile finished");
+}
+
+async fn reconcile_channel(
+    storage: &dyn Storage,
+    settings: &Settings,
+    http: &Http,
+    live_ranges: &DashMap<ChannelId, database::Range>,
+    channel_id: ChannelId,
+) -> Result<(), Error> {
+    // 아직 아무것도 캐시 안된 채널은 백필이 처리
+    let Some(range) = storage.latest_sync_range(channel_id.get() as i64).await? else {
+        return Ok(());
+    };
+
+    let guild_id = match http.get_channel(channel_id).await? {
+        Channel::Guild(channel) => channel.guild_id,
+        _ => return Ok(()),
+    };
+    let redactor = settings.redactor(guild_id).await?;
+    let guild_id = guild_id.get() as i64;
+
+    recheck_recent(storage, http, channel_id, guild_id, &redactor, range).await?;
+    let Some(head) = fetch_newer(storage, http, channel_id, guild_id, &redactor, range).await?
+    else {
+        // 최신까지 못 따라잡음. 이어지지 않은 구간을 live로 쓰면 안됨
+        return Ok(());
+    };
+
+    // reconcile 도중 들어온 메세지로 이미 세션이 생겼을 수 있음
+    // Ready 이후 메세지는 전부 이벤트로 받으니 head와 세션 사이에 빠진 메세지는 없음
+    let session = *live_ranges
+        .entry(channel_id)
+        .and_modify(|session| {
+            *session =
+                database::Range::new(min(session.start, head.start), max(session.end, head.end))
+        })
+        .or_insert(head);
+    storage
+        .add_sync_range(channel_id.get() as i64, session.start, session.end)
+        .await?;
+
+    Ok(())
+}
+
+// 마지막 sync range 이후 메세지를 앞으로 가져옴. 항상 range 끝에 이어지므로 연속 구간 유지
+// 최신 메세지까지 가져왔으면 그 구간을 반환
+async fn fetch_newer(
+    storage: &dyn Storage,
+    http: &Http,
+    channel_id: ChannelId,
+    guild_id: i64,
+    redactor: &Redactor,
+    range: database::Range,
+) -> Result<Option<database::Range>, Error> {
+    let mut head = range;
+    let mut after = range.end;
+    for _ in 0..MAX_FORWARD_PAGES {
+        let messages = channel_id
+            .messages(
+                http,
+                GetMessages::new()
+                    .limit(PAGE_SIZE)
+                    .after(MessageId::new(after as u64)),
+            )
+            .await?;
+
+        let Some(max_id) = messages.iter().map(|m| m.id.get() as i64).max() else {
+            return Ok(Some(head));
+        };
+
+        storage
+            .insert_messages(&messages, guild_id, redactor)
+            .await?;
+        head = storage
+            .add_sync_range(channel_id.get() as i64, after, max_id)
+            .await?;
+        after = max_id;
+
+        if messages.len() < PAGE_SIZE as usize {
+            return Ok(Some(head));
+        }
+    }
+    Ok(None)
+}
+
+// 캐시된 최근 구간을 다시 가져와서 수정된 내용은 덮어쓰고 사라진 메세지는 삭제
+async fn recheck_recent(
+    storage: &dyn Storage,
+    http: &Http,
+    channel_id: ChannelId,
+    guild_id: i64,
+    redactor: &Redactor,
+    range: database::Range,
+) -> Result<(), Error> {
+    let mut upper = range.end; // inclusive
+    for _ in 0..RECHECK_PAGES {
+        let messages = channel_id
+            .messages(
+                http,
+                GetMessages::new()
+                    .limit(PAGE_SIZE)
+                    .before(MessageId::new(upper as u64 + 1)),
+            )
+            .await?;
+
+        // 한 페이지를 다 못 채웠으면 채널 처음까지 온 것
+        let lower = match messages.last() {
+            Some(oldest) if messages.len() == PAGE_SIZE as usize => oldest.id.get() as i64,
+            _ => range.start,
+        };
+        let lower = max(lower, range.start);
+
+        let alive: HashSet<i64> = messages.iter().map(|m| m.id.get() as i64).collect();
+        let cached = storage
+            .list_message_ids_range(channel_id.get() as i64, lower, upper)
+            .await?;
+        for id in cached.into_iter().filter(|id| !alive.contains(id)) {
+            storage.delete_message(MessageId::new(id as u64)).await?;
+        }
+
+        let in_range: Vec<_> = messages
+            .into_iter()
+            .filter(|m| m.id.get() as i64 >= lower)
+            .collect();
+        storage
+            .insert_messages(&in_range, guild_id, redactor)
+            .await?;
+
+        if lower <= range.start {
+            break;
+        }
+        upper = lower - 1;
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::Error;
+use crate::database::SettingScope;
+use crate::storage::Storage;
+use poise::serenity_prelude::{ChannelId, GuildId};
+use std::sync::Arc;
+use std::time::Duration;
+
+// 보관 기간, 개수 제한을 넘긴 캐시를 주기적으로 정리
+pub async fn run(storage: Arc<dyn Storage>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let channels = match storage.list_caching_channels().await {
+            Ok(channels) => channels,
+            Err(e) => {
+                tracing::warn!("list_caching_channels failed: {}", e);
+                continue;
+            }
+        };
+
+        for channel_id in channels {
+            let guild_id = match storage.channel_guild_id(channel_id).await {
+                Ok(Some(guild_id)) => guild_id,
+                Ok(None) => continue, // 캐시된 메세지 없음
+                Err(e) => {
+                    tracing::warn!("channel_guild_id failed for {}: {}", channel_id, e);
+                    continue;
+                }
+            };
+
+            match prune_channel(storage.as_ref(), guild_id, channel_id).await {
+                Ok(0) => {}
+                Ok(deleted) => {
+                    tracing::info!("Pruned {} messages in channel {}", deleted, channel_id)
+                }
+                Err(e) => tracing::warn!("prune failed for channel {}: {}", channel_id, e),
+            }
+        }
+    }
+}
+
+async fn prune_channel(
+    storage: &dyn Storage,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<u64, Error> {
+    // 다른 프로세스에서 바꾼 설정도 반영되도록 캐시를 거치지 않고 읽음
+    let channel = storage
+        .load_settings(SettingScope::Channel(channel_id))
+        .await?;
+    let guild = storage.load_settings(SettingScope::Guild(guild_id)).await?;
+    let policy = channel.retention().effective(guild.retention());
+    if policy.is_empty() {
+        return Ok(0);
+    }
+
+    let mut deleted = 0;
+
+    if let Some(cutoff_id) = policy.age_cutoff_id(chrono::Utc::now().timestamp()) {
+        deleted += storage.prune_channel_before(channel_id, cutoff_id).await?;
+    }
+
+    if let Some(max_messages) = policy.max_messages
+        && let Some(oldest_kept) = storage
+            .nth_newest_message_id(channel_id, max_messages)
+            .await?
+    {
+        deleted += storage
+            .prune_channel_before(channel_id, oldest_kept)
+            .await?;
+    }
+
+    Ok(deleted)
+}
@@ -0,0 +1,319 @@
+use crate::database::{
+    BotFilter, RetentionPolicy, ScopeSettings, Setting, SettingScope, SettingValue,
+};
+use crate::redaction::{RedactionRules, Redactor};
+use crate::storage::Storage;
+use dashmap::DashMap;
+use poise::serenity_prelude::{ChannelId, GuildId};
+use std::sync::Arc;
+
+// 캐싱 설정을 찾을 채널들. 앞에 있을수록 우선
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelScope {
+    pub guild_id: Option<GuildId>,
+    // 채널 자신, (thread면) 부모 채널, category 순서
+    pub channel_ids: Vec<ChannelId>,
+}
+
+// 메세지 이벤트마다 설정을 읽으므로 scope별로 캐시. 쓰기는 전부 여기를 거쳐야 캐시가 맞음
+#[derive(Clone)]
+pub struct Settings {
+    storage: Arc<dyn Storage>,
+    cache: Arc<DashMap<SettingScope, Arc<ScopeSettings>>>,
+    // 메세지를 저장할 때마다 정규식을 다시 컴파일하지 않도록 서버별로 캐시
+    redactors: Arc<DashMap<GuildId, Arc<Redactor>>>,
+}
+
+impl Settings {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            cache: Arc::new(DashMap::new()),
+            redactors: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub async fn get(&self, scope: SettingScope) -> Result<Arc<ScopeSettings>, sqlx::Error> {
+        if let Some(settings) = self.cache.get(&scope) {
+            return Ok(settings.clone());
+        }
+        let settings = Arc::new(self.storage.load_settings(scope).await?);
+        self.cache.insert(scope, settings.clone());
+        Ok(settings)
+    }
+
+    pub async fn set(
+        &self,
+        guild_id: GuildId,
+        scope: SettingScope,
+        setting: Setting,
+        value: Option<SettingValue>,
+    ) -> Result<(), sqlx::Error> {
+        self.storage
+            .save_setting(guild_id, scope, setting, value)
+            .await?;
+        self.cache.remove(&scope);
+        Ok(())
+    }
+
+    // DB에서 직접 지운 경우 (채널 삭제, 서버 추방)
+    pub fn invalidate_all(&self) {
+        self.cache.clear();
+        self.redactors.clear();
+    }
+
+    // channel -> category -> guild 순서로 처음 명시된 설정과 그 scope
+    pub async fn resolve_caching(
+        &self,
+        scope: &ChannelScope,
+    ) -> Result<Option<(SettingScope, bool)>, sqlx::Error> {
+        let scopes = scope
+            .channel_ids
+            .iter()
+            .map(|id| SettingScope::Channel(*id))
+            .chain(scope.guild_id.map(SettingScope::Guild));
+        for scope in scopes {
+            if let Some(enabled) = self.get(scope).await?.caching() {
+                return Ok(Some((scope, enabled)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub async fn is_caching_enabled(&self, scope: &ChannelScope) -> Result<bool, sqlx::Error> {
+        Ok(self
+            .resolve_caching(scope)
+            .await?
+            .is_some_and(|(_, enabled)| enabled))
+    }
+
+    pub async fn is_revisions_enabled(&self, guild_id: GuildId) -> Result<bool, sqlx::Error> {
+        Ok(self.get(SettingScope::Guild(guild_id)).await?.revisions())
+    }
+
+    pub async fn bot_filter(&self, channel_id: ChannelId) -> Result<BotFilter, sqlx::Error> {
+        Ok(self
+            .get(SettingScope::Channel(channel_id))
+            .await?
+            .bot_filter())
+    }
+
+    pub async fn set_bot_filter(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        filter: &BotFilter,
+    ) -> Result<(), sqlx::Error> {
+        let scope = SettingScope::Channel(channel_id);
+        let mode = SettingValue::Text(filter.mode.as_str().to_owned());
+        let allowlist = SettingValue::Ids(filter.allowlist.clone());
+        self.set(guild_id, scope, Setting::Bots, Some(mode)).await?;
+        self.set(guild_id, scope, Setting::BotAllowlist, Some(allowlist))
+            .await
+    }
+
+    // (채널, 서버) 보관 정책
+    pub async fn retention(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+    ) -> Result<(RetentionPolicy, RetentionPolicy), sqlx::Error> {
+        let channel = self.get(SettingScope::Channel(channel_id)).await?;
+        let guild = self.get(SettingScope::Guild(guild_id)).await?;
+        Ok((channel.retention(), guild.retention()))
+    }
+
+    // None인 항목은 그대로 두고, 0 이하면 제한 해제
+    pub async fn set_retention(
+        &self,
+        guild_id: GuildId,
+        scope: SettingScope,
+        max_age_days: Option<i64>,
+        max_messages: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        for (setting, value) in [
+            (Setting::RetentionDays, max_age_days),
+            (Setting::RetentionCount, max_messages),
+        ] {
+            if let Some(value) = value {
+                let value = (value > 0).then_some(SettingValue::Int(value));
+                self.set(guild_id, scope, setting, value).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn redaction(&self, guild_id: GuildId) -> Result<RedactionRules, sqlx::Error> {
+        Ok(self.get(SettingScope::Guild(guild_id)).await?.redaction())
+    }
+
+    pub async fn redactor(&self, guild_id: GuildId) -> Result<Arc<Redactor>, sqlx::Error> {
+        if let Some(redactor) = self.redactors.get(&guild_id) {
+            return Ok(redactor.clone());
+        }
+        let redactor = Arc::new(Redactor::new(&self.redaction(guild_id).await?));
+        self.redactors.insert(guild_id, redactor.clone());
+        Ok(redactor)
+    }
+
+    pub async fn set_redaction(
+        &self,
+        guild_id: GuildId,
+        rules: &RedactionRules,
+    ) -> Result<(), sqlx::Error> {
+        let scope = SettingScope::Guild(guild_id);
+        let detectors = rules
+            .detectors
+            .iter()
+            .map(|d| d.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        let patterns = rules.patterns.join("\n");
+        for (setting, value) in [
+            (Setting::Redaction, detectors),
+            (Setting::RedactionPatterns, patterns),
+        ] {
+            let value = (!value.is_empty()).then_some(SettingValue::Text(value));
+            self.set(guild_id, scope, setting, value).await?;
+        }
+        self.redactors.remove(&guild_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::redaction::Detector;
+    use crate::storage::SqliteStorage;
+
+    #[tokio::test]
+    async fn channel_overrides_category_overrides_guild() {
+        let settings = Settings::new(Arc::new(SqliteStorage::new(database::memory_pool().await)));
+        let guild_id = GuildId::new(1);
+        let channel_id = ChannelId::new(10);
+        let category_id = ChannelId::new(20);
+        let scope = ChannelScope {
+            guild_id: Some(guild_id),
+            channel_ids: vec![channel_id, category_id],
+        };
+        let caching = |enabled| Some(SettingValue::Bool(enabled));
+
+        assert!(!settings.is_caching_enabled(&scope).await.unwrap());
+
+        settings
+            .set(
+                guild_id,
+                SettingScope::Guild(guild_id),
+                Setting::Caching,
+                caching(true),
+            )
+            .await
+            .unwrap();
+        assert!(settings.is_caching_enabled(&scope).await.unwrap());
+
+        settings
+            .set(
+                guild_id,
+                SettingScope::Channel(category_id),
+                Setting::Caching,
+                caching(false),
+            )
+            .await
+            .unwrap();
+        assert!(!settings.is_caching_enabled(&scope).await.unwrap());
+
+        settings
+            .set(
+                guild_id,
+                SettingScope::Channel(channel_id),
+                Setting::Caching,
+                caching(true),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            settings.resolve_caching(&scope).await.unwrap(),
+            Some((SettingScope::Channel(channel_id), true))
+        );
+
+        settings
+            .set(
+                guild_id,
+                SettingScope::Channel(channel_id),
+                Setting::Caching,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(!settings.is_caching_enabled(&scope).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn redaction_rules_round_trip() {
+        let settings = Settings::new(Arc::new(SqliteStorage::new(database::memory_pool().await)));
+        let guild_id = GuildId::new(1);
+        let rules = RedactionRules {
+            detectors: vec![Detector::Tokens, Detector::Emails],
+            patterns: vec![r"사내코드-\d+".to_owned(), "a,b".to_owned()],
+        };
+
+        assert!(settings.redactor(guild_id).await.unwrap().is_empty());
+        settings.set_redaction(guild_id, &rules).await.unwrap();
+        assert_eq!(settings.redaction(guild_id).await.unwrap(), rules);
+        let redactor = settings.redactor(guild_id).await.unwrap();
+        assert_eq!(redactor.redact("사내코드-42"), "[가려짐]");
+
+        settings
+            .set_redaction(guild_id, &RedactionRules::default())
+            .await
+            .unwrap();
+        assert!(settings.redaction(guild_id).await.unwrap().is_empty());
+        assert!(settings.redactor(guild_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn legacy_config_is_migrated() {
+        let pool = database::memory_pool().await;
+        // 옮겨진 뒤라 config 테이블은 없음. 같은 변환을 다시 거치도록 임시로 만듦
+        sqlx::query("CREATE TABLE config (key TEXT PRIMARY KEY, value TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO config (key, value) VALUES
+             ('channel:10:caching', 'true'),
+             ('guild:1:revisions', 'true'),
+             ('guild:1:retention_days', '30'),
+             ('channel:10:bots', 'webhooks'),
+             ('channel:10:bot_allowlist', '5,6')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::raw_sql(include_str!("../migrations/20261019000005_settings.sql"))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let settings = Settings::new(Arc::new(SqliteStorage::new(pool)));
+        let channel = settings
+            .get(SettingScope::Channel(ChannelId::new(10)))
+            .await
+            .unwrap();
+        assert_eq!(channel.caching(), Some(true));
+        assert_eq!(channel.bot_filter().allowlist, vec![5, 6]);
+        assert!(
+            settings
+                .is_revisions_enabled(GuildId::new(1))
+                .await
+                .unwrap()
+        );
+        let (_, guild) = settings
+            .retention(GuildId::new(1), ChannelId::new(10))
+            .await
+            .unwrap();
+        assert_eq!(guild.max_age_days, Some(30));
+    }
+}
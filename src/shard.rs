@@ -0,0 +1,187 @@
+use poise::serenity_prelude::{Client, GuildId, ShardInfo, ShardManager, utils};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 이 프로세스가 띄울 shard
+// count는 전체 shard 수이고 auto면 discord 권장 개수. 기본값은 1
+// range=first-last를 주면 전체 중 그 범위만 띄움. 여러 프로세스로 나눌 때
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sharding {
+    Auto,
+    Count(u32),
+    Range { first: u32, last: u32, total: u32 },
+}
+
+impl Sharding {
+    pub fn parse(count: Option<&str>, range: Option<&str>) -> Result<Self, String> {
+        let count = match count.map(str::trim) {
+            None | Some("") => None,
+            Some("auto") => Some(Sharding::Auto),
+            Some(v) => match v.parse::<u32>() {
+                Ok(n) if n > 0 => Some(Sharding::Count(n)),
+                _ => {
+                    return Err(format!(
+                        "SHARD_COUNT must be a positive number or auto: {}",
+                        v
+                    ));
+                }
+            },
+        };
+
+        let Some(range) = range.map(str::trim).filter(|v| !v.is_empty()) else {
+            return Ok(count.unwrap_or(Sharding::Count(1)));
+        };
+        let Some(Sharding::Count(total)) = count else {
+            return Err(
+                "SHARD_RANGE requires SHARD_COUNT set to the total number of shards".into(),
+            );
+        };
+        let parsed = match range.split_once('-') {
+            Some((first, last)) => first.trim().parse().ok().zip(last.trim().parse().ok()),
+            None => range.parse().ok().map(|id| (id, id)),
+        };
+        match parsed {
+            Some((first, last)) if first <= last && last < total => {
+                Ok(Sharding::Range { first, last, total })
+            }
+            _ => Err(format!(
+                "SHARD_RANGE must be first-last within 0-{}: {}",
+                total - 1,
+                range
+            )),
+        }
+    }
+
+    // 백필, 보존 기간 정리, 백업, DB 정리, 버전 확인, 전역 명령어 등록은 shard 0을 띄우는 프로세스만
+    pub fn runs_global_jobs(&self) -> bool {
+        match self {
+            Sharding::Range { first, .. } => *first == 0,
+            Sharding::Auto | Sharding::Count(_) => true,
+        }
+    }
+
+    pub async fn start(&self, client: &mut Client) -> Result<(), poise::serenity_prelude::Error> {
+        match *self {
+            Sharding::Auto => client.start_autosharded().await,
+            Sharding::Count(total) => client.start_shards(total).await,
+            // serenity는 range.end까지 포함해서 띄움
+            Sharding::Range { first, last, total } => {
+                client.start_shard_range(first..last, total).await
+            }
+        }
+    }
+}
+
+impl Default for Sharding {
+    fn default() -> Self {
+        Sharding::Count(1)
+    }
+}
+
+impl fmt::Display for Sharding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sharding::Auto => write!(f, "recommended shard count"),
+            Sharding::Count(total) => write!(f, "{} shards", total),
+            Sharding::Range { first, last, total } => {
+                write!(f, "shards {}-{} of {}", first, last, total)
+            }
+        }
+    }
+}
+
+// guild의 이벤트를 받는 shard인지
+pub fn owns(shard: ShardInfo, guild_id: GuildId) -> bool {
+    utils::shard_id(guild_id, shard.total) == shard.id.0
+}
+
+// 이 프로세스가 띄운 shard의 연결 상태와 heartbeat 지연 시간을 주기적으로 기록
+pub async fn log_status(manager: Arc<ShardManager>, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        let runners = manager.runners.lock().await;
+        let mut shards: Vec<_> = runners.iter().collect();
+        shards.sort_by_key(|(id, _)| id.0);
+        for (id, runner) in shards {
+            match runner.latency {
+                Some(latency) => tracing::info!(
+                    "Shard {} {}, latency {}ms",
+                    id.0,
+                    runner.stage,
+                    latency.as_millis()
+                ),
+                None => tracing::info!("Shard {} {}", id.0, runner.stage),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poise::serenity_prelude::ShardId;
+
+    #[test]
+    fn parses_shard_settings() {
+        assert_eq!(Sharding::parse(None, None), Ok(Sharding::Count(1)));
+        assert_eq!(Sharding::parse(Some("auto"), None), Ok(Sharding::Auto));
+        assert_eq!(Sharding::parse(Some("4"), None), Ok(Sharding::Count(4)));
+        assert_eq!(
+            Sharding::parse(Some("10"), Some("4-7")),
+            Ok(Sharding::Range {
+                first: 4,
+                last: 7,
+                total: 10
+            })
+        );
+        assert_eq!(
+            Sharding::parse(Some("10"), Some("9")),
+            Ok(Sharding::Range {
+                first: 9,
+                last: 9,
+                total: 10
+            })
+        );
+
+        assert!(Sharding::parse(Some("0"), None).is_err());
+        assert!(Sharding::parse(Some("many"), None).is_err());
+        assert!(Sharding::parse(None, Some("0-1")).is_err());
+        assert!(Sharding::parse(Some("auto"), Some("0-1")).is_err());
+        assert!(Sharding::parse(Some("4"), Some("2-4")).is_err());
+        assert!(Sharding::parse(Some("4"), Some("3-2")).is_err());
+    }
+
+    #[test]
+    fn only_first_shard_runs_global_jobs() {
+        assert!(Sharding::Auto.runs_global_jobs());
+        assert!(Sharding::Count(4).runs_global_jobs());
+        let range = |first| Sharding::Range {
+            first,
+            last: 3,
+            total: 4,
+        };
+        assert!(range(0).runs_global_jobs());
+        assert!(!range(2).runs_global_jobs());
+    }
+
+    #[test]
+    fn every_guild_has_one_owner() {
+        for id in [1u64, 81384788765712384, 1 << 40] {
+            let guild_id = GuildId::new(id);
+            let owners = (0..4)
+                .filter(|&shard| {
+                    owns(
+                        ShardInfo {
+                            id: ShardId(shard),
+                            total: 4,
+                        },
+                        guild_id,
+                    )
+                })
+                .count();
+            assert_eq!(owners, 1);
+        }
+    }
+}
@@ -0,0 +1,779 @@
+use crate::Error;
+use crate::database::{
+    self, CacheStats, MessageRevision, Range, ScopeSettings, SearchResult, Setting, SettingScope,
+    SettingValue, UserData,
+};
+use crate::ingest::{self, Write};
+use crate::postgres::PostgresStorage;
+use crate::redaction::Redactor;
+use async_trait::async_trait;
+use poise::serenity_prelude::{
+    ChannelId, GuildId, GuildMemberUpdateEvent, Message, MessageId, UserId,
+};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+// 봇이 실행 중에 쓰는 저장소. 메세지, sync range, 설정, 버전 알림 구독
+// DATABASE_URL이 postgres:// 로 시작하면 PostgreSQL, 아니면 SQLite
+#[async_trait]
+pub trait Storage: Send + Sync {
+    fn backend(&self) -> &'static str;
+
+    // 기록 채우기, 보존 기간 정리, 아카이브, DB 정리, 백업은 SQLite에서만 됨
+    fn sqlite(&self) -> Option<&SqlitePool> {
+        None
+    }
+
+    // 저장소가 차지하는 크기 (byte)
+    async fn database_size(&self) -> Result<i64, sqlx::Error>;
+
+    // 메세지
+
+    // ingest writer가 모은 쓰기를 transaction 하나로 반영. Flush는 무시
+    // 가리기 규칙은 Settings에 캐시된 것을 서버 id별로 받음
+    async fn apply_writes(
+        &self,
+        batch: &[Write],
+        redactors: &HashMap<i64, Arc<Redactor>>,
+    ) -> Result<(), Error>;
+
+    async fn insert_messages(
+        &self,
+        msgs: &[Message],
+        guild_id: i64,
+        redactor: &Redactor,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn delete_message(&self, message_id: MessageId) -> Result<(), sqlx::Error>;
+
+    async fn update_member(&self, event: &GuildMemberUpdateEvent) -> Result<(), sqlx::Error>;
+
+    #[allow(clippy::too_many_arguments)]
+    async fn search_messages_range(
+        &self,
+        guild_id: i64,
+        channel_id: i64,
+        query: &str,
+        min_id: i64,
+        max_id: i64,
+        limit: u32,
+        include_revisions: bool,
+    ) -> Result<Vec<SearchResult>, sqlx::Error>;
+
+    async fn get_message(&self, message_id: i64) -> Result<Option<SearchResult>, sqlx::Error>;
+
+    async fn get_replies(
+        &self,
+        message_id: i64,
+        limit: u32,
+    ) -> Result<Vec<SearchResult>, sqlx::Error>;
+
+    async fn list_message_revisions(
+        &self,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>, sqlx::Error>;
+
+    async fn messages_with_revisions(&self, message_ids: &[i64]) -> Result<Vec<i64>, sqlx::Error>;
+
+    async fn delete_guild_revisions(&self, guild_id: GuildId) -> Result<(), sqlx::Error>;
+
+    async fn redact_guild_messages(
+        &self,
+        guild_id: GuildId,
+        redactor: &Redactor,
+    ) -> Result<u64, sqlx::Error>;
+
+    async fn count_channel_messages(&self, channel_id: ChannelId) -> Result<i64, sqlx::Error>;
+
+    async fn channel_cache_stats(&self, channel_id: ChannelId) -> Result<CacheStats, sqlx::Error>;
+
+    async fn list_message_ids_range(
+        &self,
+        channel_id: i64,
+        min_id: i64,
+        max_id: i64,
+    ) -> Result<Vec<i64>, sqlx::Error>;
+
+    async fn list_opted_out_users(&self, user_ids: &[i64]) -> Result<HashSet<i64>, sqlx::Error>;
+
+    async fn forget_user(&self, user_id: UserId) -> Result<u64, sqlx::Error>;
+
+    async fn collect_user_data(&self, user_id: UserId) -> Result<UserData, sqlx::Error>;
+
+    async fn list_caching_channels(&self) -> Result<Vec<ChannelId>, sqlx::Error>;
+
+    async fn list_guild_cached_channels(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<ChannelId>, sqlx::Error>;
+
+    async fn purge_channel(&self, channel_id: ChannelId) -> Result<(), sqlx::Error>;
+
+    async fn forget_channel(&self, channel_id: ChannelId) -> Result<(), sqlx::Error>;
+
+    async fn forget_guild(
+        &self,
+        guild_id: GuildId,
+        known_channels: &[ChannelId],
+    ) -> Result<(), sqlx::Error>;
+
+    async fn list_known_guilds(&self) -> Result<Vec<GuildId>, sqlx::Error>;
+
+    // sync range
+
+    async fn add_sync_range(
+        &self,
+        channel_id: i64,
+        start: i64,
+        end: i64,
+    ) -> Result<Range, sqlx::Error>;
+
+    async fn latest_sync_range(&self, channel_id: i64) -> Result<Option<Range>, sqlx::Error>;
+
+    async fn list_sync_ranges(&self, channel_id: i64) -> Result<Vec<Range>, sqlx::Error>;
+
+    // 보존 기간 정리
+
+    async fn channel_guild_id(&self, channel_id: ChannelId)
+    -> Result<Option<GuildId>, sqlx::Error>;
+
+    async fn nth_newest_message_id(
+        &self,
+        channel_id: ChannelId,
+        n: i64,
+    ) -> Result<Option<i64>, sqlx::Error>;
+
+    // min_id보다 오래된 메세지를 지우고 sync range도 min_id부터 시작하도록 줄임
+    async fn prune_channel_before(
+        &self,
+        channel_id: ChannelId,
+        min_id: i64,
+    ) -> Result<u64, sqlx::Error>;
+
+    // 설정
+
+    async fn load_settings(&self, scope: SettingScope) -> Result<ScopeSettings, sqlx::Error>;
+
+    async fn save_setting(
+        &self,
+        guild_id: GuildId,
+        scope: SettingScope,
+        setting: Setting,
+        value: Option<SettingValue>,
+    ) -> Result<(), sqlx::Error>;
+
+    // 여러 채널 중 해당 설정이 명시된 채널만
+    async fn list_channel_settings(
+        &self,
+        channel_ids: &[ChannelId],
+        setting: Setting,
+    ) -> Result<Vec<(ChannelId, ScopeSettings)>, sqlx::Error> {
+        let mut result = Vec::new();
+        for channel_id in channel_ids {
+            let settings = self
+                .load_settings(SettingScope::Channel(*channel_id))
+                .await?;
+            if settings.get(setting).is_some() {
+                result.push((*channel_id, settings));
+            }
+        }
+        Ok(result)
+    }
+
+    // 버전 알림 구독
+
+    async fn set_version_subscription(
+        &self,
+        user_id: u64,
+        enabled: bool,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn list_version_subscribers(&self) -> Result<Vec<i64>, sqlx::Error>;
+
+    async fn should_notify_version(
+        &self,
+        user_id: u64,
+        latest_version: &str,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn mark_version_notified(
+        &self,
+        user_id: u64,
+        latest_version: &str,
+    ) -> Result<(), sqlx::Error>;
+}
+
+pub async fn connect() -> Result<Arc<dyn Storage>, Error> {
+    let url = database::database_url();
+    if is_postgres_url(&url) {
+        Ok(Arc::new(PostgresStorage::connect(&url).await?))
+    } else {
+        Ok(Arc::new(SqliteStorage::new(database::init_db().await?)))
+    }
+}
+
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+// database.rs의 함수를 그대로 씀
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn sqlite(&self) -> Option<&SqlitePool> {
+        Some(&self.pool)
+    }
+
+    async fn database_size(&self) -> Result<i64, sqlx::Error> {
+        database::database_size(&self.pool).await
+    }
+
+    async fn apply_writes(
+        &self,
+        batch: &[Write],
+        redactors: &HashMap<i64, Arc<Redactor>>,
+    ) -> Result<(), Error> {
+        ingest::apply(&self.pool, batch, redactors).await
+    }
+
+    async fn insert_messages(
+        &self,
+        msgs: &[Message],
+        guild_id: i64,
+        redactor: &Redactor,
+    ) -> Result<(), sqlx::Error> {
+        database::insert_messages(&self.pool, msgs, guild_id, redactor).await
+    }
+
+    async fn delete_message(&self, message_id: MessageId) -> Result<(), sqlx::Error> {
+        database::delete_message(&self.pool, message_id).await
+    }
+
+    async fn update_member(&self, event: &GuildMemberUpdateEvent) -> Result<(), sqlx::Error> {
+        database::update_member(&self.pool, event).await
+    }
+
+    async fn search_messages_range(
+        &self,
+        guild_id: i64,
+        channel_id: i64,
+        query: &str,
+        min_id: i64,
+        max_id: i64,
+        limit: u32,
+        include_revisions: bool,
+    ) -> Result<Vec<SearchResult>, sqlx::Error> {
+        database::search_messages_range(
+            &self.pool,
+            guild_id,
+            channel_id,
+            query,
+            min_id,
+            max_id,
+            limit,
+            include_revisions,
+        )
+        .await
+    }
+
+    async fn get_message(&self, message_id: i64) -> Result<Option<SearchResult>, sqlx::Error> {
+        database::get_message(&self.pool, message_id).await
+    }
+
+    async fn get_replies(
+        &self,
+        message_id: i64,
+        limit: u32,
+    ) -> Result<Vec<SearchResult>, sqlx::Error> {
+        database::get_replies(&self.pool, message_id, limit).await
+    }
+
+    async fn list_message_revisions(
+        &self,
+        message_id: i64,
+    ) -> Result<Vec<MessageRevision>, sqlx::Error> {
+        database::list_message_revisions(&self.pool, message_id).await
+    }
+
+    async fn messages_with_revisions(&self, message_ids: &[i64]) -> Result<Vec<i64>, sqlx::Error> {
+        database::messages_with_revisions(&self.pool, message_ids).await
+    }
+
+    async fn delete_guild_revisions(&self, guild_id: GuildId) -> Result<(), sqlx::Error> {
+        database::delete_guild_revisions(&self.pool, guild_id).await
+    }
+
+    async fn redact_guild_messages(
+        &self,
+        guild_id: GuildId,
+        redactor: &Redactor,
+    ) -> Result<u64, sqlx::Error> {
+        database::redact_guild_messages(&self.pool, guild_id, redactor).await
+    }
+
+    async fn count_channel_messages(&self, channel_id: ChannelId) -> Result<i64, sqlx::Error> {
+        database::count_channel_messages(&self.pool, channel_id).await
+    }
+
+    async fn channel_cache_stats(&self, channel_id: ChannelId) -> Result<CacheStats, sqlx::Error> {
+        database::channel_cache_stats(&self.pool, channel_id).await
+    }
+
+    async fn list_message_ids_range(
+        &self,
+        channel_id: i64,
+        min_id: i64,
+        max_id: i64,
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        database::list_message_ids_range(&self.pool, channel_id, min_id, max_id).await
+    }
+
+    async fn list_opted_out_users(&self, user_ids: &[i64]) -> Result<HashSet<i64>, sqlx::Error> {
+        database::list_opted_out_users(&self.pool, user_ids).await
+    }
+
+    async fn forget_user(&self, user_id: UserId) -> Result<u64, sqlx::Error> {
+        database::forget_user(&self.pool, user_id).await
+    }
+
+    async fn collect_user_data(&self, user_id: UserId) -> Result<UserData, sqlx::Error> {
+        database::collect_user_data(&self.pool, user_id).await
+    }
+
+    async fn list_caching_channels(&self) -> Result<Vec<ChannelId>, sqlx::Error> {
+        database::list_caching_channels(&self.pool).await
+    }
+
+    async fn list_guild_cached_channels(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<Vec<ChannelId>, sqlx::Error> {
+        database::list_guild_cached_channels(&self.pool, guild_id).await
+    }
+
+    async fn purge_channel(&self, channel_id: ChannelId) -> Result<(), sqlx::Error> {
+        database::purge_channel(&self.pool, channel_id).await
+    }
+
+    async fn forget_channel(&self, channel_id: ChannelId) -> Result<(), sqlx::Error> {
+        database::forget_channel(&self.pool, channel_id).await
+    }
+
+    async fn forget_guild(
+        &self,
+        guild_id: GuildId,
+        known_channels: &[ChannelId],
+    ) -> Result<(), sqlx::Error> {
+        database::forget_guild(&self.pool, guild_id, known_channels).await
+    }
+
+    async fn list_known_guilds(&self) -> Result<Vec<GuildId>, sqlx::Error> {
+        database::list_known_guilds(&self.pool).await
+    }
+
+    async fn add_sync_range(
+        &self,
+        channel_id: i64,
+        start: i64,
+        end: i64,
+    ) -> Result<Range, sqlx::Error> {
+        database::add_sync_range(&self.pool, channel_id, start, end).await
+    }
+
+    async fn latest_sync_range(&self, channel_id: i64) -> Result<Option<Range>, sqlx::Error> {
+        database::latest_sync_range(&self.pool, channel_id).await
+    }
+
+    async fn list_sync_ranges(&self, channel_id: i64) -> Result<Vec<Range>, sqlx::Error> {
+        database::list_sync_ranges(&self.pool, channel_id).await
+    }
+
+    async fn channel_guild_id(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result<Option<GuildId>, sqlx::Error> {
+        database::channel_guild_id(&self.pool, channel_id).await
+    }
+
+    async fn nth_newest_message_id(
+        &self,
+        channel_id: ChannelId,
+        n: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        database::nth_newest_message_id(&self.pool, channel_id, n).await
+    }
+
+    async fn prune_channel_before(
+        &self,
+        channel_id: ChannelId,
+        min_id: i64,
+    ) -> Result<u64, sqlx::Error> {
+        database::prune_channel_before(&self.pool, channel_id, min_id).await
+    }
+
+    async fn load_settings(&self, scope: SettingScope) -> Result<ScopeSettings, sqlx::Error> {
+        database::load_settings(&self.pool, scope).await
+    }
+
+    async fn save_setting(
+        &self,
+        guild_id: GuildId,
+        scope: SettingScope,
+        setting: Setting,
+        value: Option<SettingValue>,
+    ) -> Result<(), sqlx::Error> {
+        database::save_setting(&self.pool, guild_id, scope, setting, value).await
+    }
+
+    async fn set_version_subscription(
+        &self,
+        user_id: u64,
+        enabled: bool,
+    ) -> Result<(), sqlx::Error> {
+        database::set_version_subscription(&self.pool, user_id, enabled).await
+    }
+
+    async fn list_version_subscribers(&self) -> Result<Vec<i64>, sqlx::Error> {
+        database::list_version_subscribers(&self.pool).await
+    }
+
+    async fn should_notify_version(
+        &self,
+        user_id: u64,
+        latest_version: &str,
+    ) -> Result<bool, sqlx::Error> {
+        database::should_notify_version(&self.pool, user_id, latest_version).await
+    }
+
+    async fn mark_version_notified(
+        &self,
+        user_id: u64,
+        latest_version: &str,
+    ) -> Result<(), sqlx::Error> {
+        database::mark_version_notified(&self.pool, user_id, latest_version).await
+    }
+}
+
+// 두 저장소가 같은 동작을 하는지 확인
+// PostgreSQL은 TEST_POSTGRES_URL이 필요해서 --ignored로 따로 실행. 테스트마다 새 schema를 만들어서 씀
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::BotCaching;
+    use poise::serenity_prelude::{self as serenity, MessageUpdateEvent};
+
+    const GUILD: u64 = 100;
+    const CHANNEL: u64 = 10;
+
+    fn message(id: u64, author: u64, content: &str) -> Message {
+        let mut msg = Message::default();
+        msg.id = MessageId::new(id);
+        msg.channel_id = ChannelId::new(CHANNEL);
+        msg.guild_id = Some(GuildId::new(GUILD));
+        msg.author.id = UserId::new(author);
+        msg.author.name = format!("user{author}");
+        msg.content = content.to_owned();
+        msg
+    }
+
+    fn edit(id: u64, content: &str) -> MessageUpdateEvent {
+        serenity::json::from_value(serenity::json::json!({
+            "id": id.to_string(),
+            "channel_id": CHANNEL.to_string(),
+            "guild_id": GUILD.to_string(),
+            "content": content,
+        }))
+        .unwrap()
+    }
+
+    fn range(channel_id: u64, start: i64, end: i64) -> Write {
+        Write::Range {
+            channel_id: ChannelId::new(channel_id),
+            range: Range::new(start, end),
+        }
+    }
+
+    async fn search(storage: &dyn Storage, query: &str, revisions: bool) -> Vec<i64> {
+        storage
+            .search_messages_range(
+                GUILD as i64,
+                CHANNEL as i64,
+                query,
+                0,
+                i64::MAX,
+                10,
+                revisions,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.message_id)
+            .collect()
+    }
+
+    async fn contract(storage: &dyn Storage) {
+        let guild_id = GuildId::new(GUILD);
+        let channel_id = ChannelId::new(CHANNEL);
+
+        // 설정
+        storage
+            .save_setting(
+                guild_id,
+                SettingScope::Guild(guild_id),
+                Setting::Redaction,
+                Some(SettingValue::Text("emails".to_owned())),
+            )
+            .await
+            .unwrap();
+        storage
+            .save_setting(
+                guild_id,
+                SettingScope::Channel(channel_id),
+                Setting::Bots,
+                Some(SettingValue::Text(BotCaching::All.as_str().to_owned())),
+            )
+            .await
+            .unwrap();
+        storage
+            .save_setting(
+                guild_id,
+                SettingScope::Channel(channel_id),
+                Setting::BotAllowlist,
+                Some(SettingValue::Ids(vec![1, 2])),
+            )
+            .await
+            .unwrap();
+        let channel = storage
+            .load_settings(SettingScope::Channel(channel_id))
+            .await
+            .unwrap();
+        assert_eq!(channel.bot_filter().mode, BotCaching::All);
+        assert_eq!(channel.bot_filter().allowlist, vec![1, 2]);
+        storage
+            .save_setting(
+                guild_id,
+                SettingScope::Channel(channel_id),
+                Setting::Bots,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage
+                .list_channel_settings(&[channel_id, ChannelId::new(11)], Setting::BotAllowlist)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // 메세지. 봇 메세지는 채널 설정에 따라 걸러지고, 내용은 가려서 저장
+        let guild = storage
+            .load_settings(SettingScope::Guild(guild_id))
+            .await
+            .unwrap();
+        let emails = Arc::new(Redactor::new(&guild.redaction()));
+        let redactors = HashMap::from([(GUILD as i64, emails.clone())]);
+        let mut bot = message(4, 9, "봇 메세지");
+        bot.author.bot = true;
+        let mut reply = message(3, 8, "답장 c@example.com");
+        reply.message_reference = Some(serenity::MessageReference::from((
+            channel_id,
+            MessageId::new(1),
+        )));
+        storage
+            .apply_writes(
+                &[
+                    Write::Insert(Box::new(message(1, 7, "안녕 a@example.com"))),
+                    Write::Insert(Box::new(message(2, 7, "두번째 메세지"))),
+                    Write::Insert(Box::new(reply)),
+                    Write::Insert(Box::new(bot)),
+                    range(CHANNEL, 1, 2),
+                    range(CHANNEL, 3, 4),
+                    range(CHANNEL, 10, 12),
+                    Write::Update {
+                        event: Box::new(edit(2, "고친 메세지")),
+                        keep_revision: true,
+                    },
+                    Write::Delete(vec![MessageId::new(5)]),
+                ],
+                &redactors,
+            )
+            .await
+            .unwrap();
+
+        let first = storage.get_message(1).await.unwrap().unwrap();
+        assert_eq!(first.content, "안녕 [가려진 이메일]");
+        assert_eq!(first.author_display_name(), "user7");
+        assert!(storage.get_message(4).await.unwrap().is_none());
+        assert_eq!(storage.count_channel_messages(channel_id).await.unwrap(), 3);
+        let stats = storage.channel_cache_stats(channel_id).await.unwrap();
+        assert_eq!(stats.count, 3);
+        assert!(stats.earliest <= stats.latest);
+        assert_eq!(
+            storage
+                .channel_cache_stats(ChannelId::new(CHANNEL + 1))
+                .await
+                .unwrap(),
+            CacheStats::default()
+        );
+        assert!(storage.database_size().await.unwrap() > 0);
+        assert_eq!(
+            storage
+                .list_message_ids_range(CHANNEL as i64, 2, 3)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert_eq!(search(storage, "메세지", false).await, vec![2]);
+        assert_eq!(search(storage, "두번째", false).await, Vec::<i64>::new());
+        assert_eq!(search(storage, "두번째", true).await, vec![2]);
+        assert_eq!(
+            storage.messages_with_revisions(&[1, 2, 3]).await.unwrap(),
+            vec![2]
+        );
+        let revisions = storage.list_message_revisions(2).await.unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].content, "두번째 메세지");
+        let replies = storage.get_replies(1, 10).await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].content, "답장 [가려진 이메일]");
+
+        // 새로 추가한 규칙은 이미 캐시된 메세지에도 적용
+        let redactor = Redactor::new(&crate::redaction::RedactionRules {
+            detectors: vec![],
+            patterns: vec!["고친".to_owned()],
+        });
+        assert_eq!(
+            storage
+                .redact_guild_messages(guild_id, &redactor)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            storage.get_message(2).await.unwrap().unwrap().content,
+            "[가려짐] 메세지"
+        );
+
+        // sync range 병합
+        assert_eq!(
+            storage.list_sync_ranges(CHANNEL as i64).await.unwrap(),
+            vec![Range::new(1, 4), Range::new(10, 12)]
+        );
+        assert_eq!(
+            storage.add_sync_range(CHANNEL as i64, 5, 9).await.unwrap(),
+            Range::new(1, 12)
+        );
+        assert_eq!(
+            storage.latest_sync_range(CHANNEL as i64).await.unwrap(),
+            Some(Range::new(1, 12))
+        );
+        assert_eq!(
+            storage.list_caching_channels().await.unwrap(),
+            vec![channel_id]
+        );
+
+        // 메세지가 지워지면 수정 기록도 같이 지워짐
+        storage.delete_message(MessageId::new(2)).await.unwrap();
+        assert!(storage.list_message_revisions(2).await.unwrap().is_empty());
+
+        // 캐싱 거부한 사용자
+        assert_eq!(storage.forget_user(UserId::new(7)).await.unwrap(), 1);
+        storage
+            .insert_messages(
+                &[message(20, 7, "다시"), message(21, 8, "다른 사람")],
+                GUILD as i64,
+                &emails,
+            )
+            .await
+            .unwrap();
+        assert!(storage.get_message(20).await.unwrap().is_none());
+        assert!(storage.get_message(21).await.unwrap().is_some());
+        assert_eq!(
+            storage.list_opted_out_users(&[7, 8]).await.unwrap(),
+            HashSet::from([7])
+        );
+        let data = storage.collect_user_data(UserId::new(8)).await.unwrap();
+        assert_eq!(data.messages.len(), 2);
+        assert_eq!(data.members.len(), 1);
+        assert!(data.opted_out_at.is_none());
+
+        // 버전 알림 구독
+        storage.set_version_subscription(8, true).await.unwrap();
+        storage.set_version_subscription(9, false).await.unwrap();
+        assert_eq!(storage.list_version_subscribers().await.unwrap(), vec![8]);
+        assert!(storage.should_notify_version(8, "1.0.0").await.unwrap());
+        storage.mark_version_notified(8, "1.0.0").await.unwrap();
+        assert!(!storage.should_notify_version(8, "1.0.0").await.unwrap());
+        assert!(!storage.should_notify_version(9, "1.0.0").await.unwrap());
+
+        // 보존 기간 정리. 지운 구간만큼 sync range도 줄어듦
+        assert_eq!(
+            storage.channel_guild_id(channel_id).await.unwrap(),
+            Some(guild_id)
+        );
+        assert_eq!(
+            storage.nth_newest_message_id(channel_id, 2).await.unwrap(),
+            Some(3)
+        );
+        assert!(
+            storage
+                .nth_newest_message_id(channel_id, 3)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            storage.prune_channel_before(channel_id, 4).await.unwrap(),
+            1
+        );
+        assert!(storage.get_message(3).await.unwrap().is_none());
+        assert_eq!(
+            storage.list_sync_ranges(CHANNEL as i64).await.unwrap(),
+            vec![Range::new(4, 12)]
+        );
+
+        // 서버를 나가면 전부 지움
+        assert_eq!(storage.list_known_guilds().await.unwrap(), vec![guild_id]);
+        storage.forget_guild(guild_id, &[]).await.unwrap();
+        assert!(storage.list_known_guilds().await.unwrap().is_empty());
+        assert!(
+            storage
+                .list_sync_ranges(CHANNEL as i64)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn sqlite_contract() {
+        contract(&SqliteStorage::new(database::memory_pool().await)).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_POSTGRES_URL"]
+    async fn postgres_contract() {
+        let url = std::env::var("TEST_POSTGRES_URL").expect("TEST_POSTGRES_URL is not set");
+        let (storage, schema) = PostgresStorage::test_schema(&url).await.unwrap();
+        contract(&storage).await;
+        storage.drop_schema(&schema).await.unwrap();
+    }
+}